@@ -0,0 +1,149 @@
+use crate::model::config::AskpassRule;
+
+/// What a password prompt from ssh, ssh-add or sudo is asking for
+#[derive(Debug, Default, PartialEq)]
+pub struct Prompt {
+    pub text: String,
+    /// Private key whose passphrase is wanted
+    pub key: Option<String>,
+    /// Host whose password is wanted
+    pub host: Option<String>,
+    pub user: Option<String>,
+}
+
+/// Pick the key path or host out of prompts such as
+/// `Enter passphrase for key '/home/me/.ssh/id_ed25519': `,
+/// `Enter passphrase for /home/me/.ssh/id_rsa (me@laptop): `,
+/// `me@build01's password: ` and `(me@build01) Password: `.
+/// sudo's `[sudo] password for me: ` names neither, rules match it by its text.
+pub fn parse(text: &str) -> Prompt {
+    let mut prompt = Prompt {
+        text: text.to_owned(),
+        ..Prompt::default()
+    };
+    if let Some(rest) = text.split_once("passphrase for key '").map(|(_, rest)| rest) {
+        prompt.key = rest.split('\'').next().map(|k| k.to_owned());
+    } else if let Some(rest) = text.split_once("passphrase for ").map(|(_, rest)| rest) {
+        let key = rest.split(" (").next().unwrap_or(rest).trim().trim_end_matches(':');
+        if key.starts_with('/') || key.starts_with('~') {
+            prompt.key = Some(key.to_owned());
+        }
+    }
+
+    let login = if let Some((before, _)) = text.split_once("'s password") {
+        before.rsplit(char::is_whitespace).next()
+    } else if text.starts_with('(') && text.to_lowercase().contains(") password") {
+        text[1..].split(')').next()
+    } else {
+        None
+    };
+    if let Some(login) = login {
+        match login.rsplit_once('@') {
+            Some((user, host)) => {
+                prompt.user = Some(user.to_owned());
+                prompt.host = Some(host.to_owned());
+            },
+            None => prompt.host = Some(login.to_owned()),
+        }
+    }
+    prompt
+}
+
+fn expand(path: &str) -> String {
+    shellexpand::tilde(path).into_owned()
+}
+
+/// A rule matches when everything it specifies matches the prompt
+pub fn matches(rule: &AskpassRule, prompt: &Prompt) -> bool {
+    if rule.key.is_none() && rule.host.is_none() && rule.prompt.is_none() {
+        return false
+    }
+    let key = rule.key.as_ref().is_none_or(|key|
+        prompt.key.as_ref().is_some_and(|k| expand(k) == expand(key))
+    );
+    let host = rule.host.as_ref().is_none_or(|host| match (host.rsplit_once('@'), &prompt.host) {
+        (_, None) => false,
+        (Some((user, host)), Some(h)) => prompt.user.as_deref() == Some(user) && h.eq_ignore_ascii_case(host),
+        (None, Some(h)) => h.eq_ignore_ascii_case(host),
+    });
+    let text = rule.prompt.as_ref().is_none_or(|text| prompt.text.contains(text.as_str()));
+    key && host && text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(key: Option<&str>, host: Option<&str>, prompt: Option<&str>) -> AskpassRule {
+        AskpassRule {
+            key: key.map(|k| k.to_owned()),
+            host: host.map(|h| h.to_owned()),
+            prompt: prompt.map(|p| p.to_owned()),
+            secret: "Item/password".to_owned(),
+        }
+    }
+
+    #[test]
+    fn ssh_key_passphrase() {
+        let prompt = parse("Enter passphrase for key '/home/me/.ssh/id_ed25519': ");
+        assert_eq!(prompt.key.as_deref(), Some("/home/me/.ssh/id_ed25519"));
+        assert_eq!(prompt.host, None);
+    }
+
+    #[test]
+    fn ssh_add_passphrase() {
+        let prompt = parse("Enter passphrase for /home/me/.ssh/id_rsa (me@laptop): ");
+        assert_eq!(prompt.key.as_deref(), Some("/home/me/.ssh/id_rsa"));
+        assert_eq!((prompt.user, prompt.host), (None, None));
+    }
+
+    #[test]
+    fn ssh_password() {
+        let prompt = parse("me@build01's password: ");
+        assert_eq!(prompt.user.as_deref(), Some("me"));
+        assert_eq!(prompt.host.as_deref(), Some("build01"));
+        assert_eq!(prompt.key, None);
+    }
+
+    #[test]
+    fn ssh_keyboard_interactive_password() {
+        let prompt = parse("(me@build01) Password: ");
+        assert_eq!(prompt.user.as_deref(), Some("me"));
+        assert_eq!(prompt.host.as_deref(), Some("build01"));
+    }
+
+    #[test]
+    fn sudo_password() {
+        let prompt = parse("[sudo] password for me: ");
+        assert_eq!(prompt, Prompt { text: "[sudo] password for me: ".to_owned(), ..Prompt::default() });
+        assert!(matches(&rule(None, None, Some("[sudo]")), &prompt));
+        assert!(!matches(&rule(None, Some("me"), None), &prompt));
+    }
+
+    #[test]
+    fn unrecognised_prompt() {
+        let prompt = parse("Username for 'https://github.com': ");
+        assert_eq!((&prompt.key, &prompt.user, &prompt.host), (&None, &None, &None));
+        assert!(!matches(&rule(None, Some("github.com"), None), &prompt));
+        // A rule asking for nothing matches nothing
+        assert!(!matches(&rule(None, None, None), &prompt));
+    }
+
+    #[test]
+    fn keys_match_with_the_home_directory_expanded() {
+        let home = shellexpand::tilde("~").into_owned();
+        let prompt = parse(&format!("Enter passphrase for key '{}/.ssh/id_ed25519': ", home));
+        assert!(matches(&rule(Some("~/.ssh/id_ed25519"), None, None), &prompt));
+        assert!(!matches(&rule(Some("~/.ssh/id_rsa"), None, None), &prompt));
+    }
+
+    #[test]
+    fn hosts_match_with_the_user_if_given() {
+        let prompt = parse("me@Build01's password: ");
+        assert!(matches(&rule(None, Some("build01"), None), &prompt));
+        assert!(matches(&rule(None, Some("me@build01"), None), &prompt));
+        assert!(!matches(&rule(None, Some("admin@build01"), None), &prompt));
+        // Everything a rule gives must match
+        assert!(!matches(&rule(None, Some("build01"), Some("[sudo]")), &prompt));
+    }
+}
@@ -0,0 +1,229 @@
+use std::env;
+use std::process::Stdio;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+use log::debug;
+use anyhow::{Result, Error, Context};
+
+use crate::commands;
+use crate::model::config::AutoType;
+use crate::model::logical::{FullField, find_named_field};
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Text(String),
+    /// Field looked up by designation or name
+    Field(String),
+    Key(Key),
+    Delay(u64),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Key {
+    Tab,
+    Enter,
+    Space,
+}
+
+impl Key {
+    // Key name as understood by xdotool and wtype
+    fn keysym(self) -> &'static str {
+        match self {
+            Key::Tab => "Tab",
+            Key::Enter => "Return",
+            Key::Space => "space",
+        }
+    }
+
+    // ydotool types these as text
+    fn text(self) -> &'static str {
+        match self {
+            Key::Tab => "\t",
+            Key::Enter => "\n",
+            Key::Space => " ",
+        }
+    }
+}
+
+enum Backend {
+    Xdotool,
+    Wtype,
+    Ydotool,
+}
+
+/// Parse a sequence such as `{username}{TAB}{password}{ENTER}`.
+///
+/// `{TAB}`, `{ENTER}` and `{SPACE}` press that key, `{DELAY 500}` waits
+/// for 500 milliseconds and any other `{name}` types the item's field with
+/// that designation or name. Text outside of braces is typed as is.
+pub fn parse(sequence: &str) -> Result<Vec<Step>> {
+    let mut steps = vec![];
+    let mut rest = sequence;
+    while !rest.is_empty() {
+        match rest.find('{') {
+            Some(0) => {
+                let end = rest.find('}')
+                    .ok_or_else(||Error::msg(format!("Unclosed brace in sequence {}", sequence)))?;
+                steps.push(parse_placeholder(&rest[1..end])?);
+                rest = &rest[end + 1..];
+            },
+            Some(start) => {
+                steps.push(Step::Text(rest[..start].to_owned()));
+                rest = &rest[start..];
+            },
+            None => {
+                steps.push(Step::Text(rest.to_owned()));
+                rest = "";
+            },
+        }
+    }
+    Ok(steps)
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Step> {
+    let placeholder = placeholder.trim();
+    let upper = placeholder.to_uppercase();
+    Ok(match upper.as_str() {
+        "" => return Err(Error::msg("Empty placeholder in sequence")),
+        "TAB" => Step::Key(Key::Tab),
+        "ENTER" => Step::Key(Key::Enter),
+        "SPACE" => Step::Key(Key::Space),
+        _ if upper == "DELAY" || upper.starts_with("DELAY ") => Step::Delay(upper["DELAY".len()..].trim().parse()
+            .with_context(||format!("Bad delay in sequence placeholder {{{}}}", placeholder))?),
+        _ => Step::Field(placeholder.to_owned()),
+    })
+}
+
+/// Type a single value into the focused window
+pub fn type_value(value: &str, config: &AutoType) -> Result<()> {
+    let backend = backend();
+    thread::sleep(Duration::from_millis(config.start_delay));
+    type_text(&backend, value, config)
+}
+
+/// Type a parsed sequence, filling in placeholders from the item's fields
+pub fn type_sequence(steps: &[Step], fields: &[FullField], config: &AutoType) -> Result<()> {
+    // Resolve every field up front so a missing one doesn't leave half a login typed
+    let values = steps.iter().map(|step| match step {
+        Step::Field(name) => find_named_field(fields, name)
+            .map(|f| Some(f.value.as_str()))
+            .ok_or_else(||Error::msg(format!("Field {} not found in item", name))),
+        _ => Ok(None),
+    }).collect::<Result<Vec<_>>>()?;
+
+    let backend = backend();
+    thread::sleep(Duration::from_millis(config.start_delay));
+    for (step, value) in steps.iter().zip(values) {
+        match step {
+            Step::Text(text) => type_text(&backend, text, config)?,
+            Step::Field(_) => type_text(&backend, value.unwrap_or_default(), config)?,
+            Step::Key(key) => press_key(&backend, *key, config)?,
+            Step::Delay(ms) => thread::sleep(Duration::from_millis(*ms)),
+        }
+    }
+    Ok(())
+}
+
+// wtype needs the compositor to support virtual keyboards, ydotool works everywhere but needs its daemon
+fn backend() -> Backend {
+    if env::var_os("WAYLAND_DISPLAY").is_none() {
+        Backend::Xdotool
+    } else if commands::exists("wtype") {
+        Backend::Wtype
+    } else {
+        Backend::Ydotool
+    }
+}
+
+fn type_text(backend: &Backend, text: &str, config: &AutoType) -> Result<()> {
+    let delay = config.key_delay.to_string();
+    // Text is fed through stdin so it never shows up in the process list
+    match backend {
+        Backend::Xdotool => run("xdotool", &["type", "--clearmodifiers", "--delay", &delay, "--file", "-"], text),
+        Backend::Wtype => run("wtype", &["-d", &delay, "-"], text),
+        Backend::Ydotool => run("ydotool", &["type", "--key-delay", &delay, "--file", "-"], text),
+    }
+}
+
+fn press_key(backend: &Backend, key: Key, config: &AutoType) -> Result<()> {
+    match backend {
+        Backend::Xdotool => run("xdotool", &["key", "--clearmodifiers", key.keysym()], ""),
+        Backend::Wtype => run("wtype", &["-k", key.keysym()], ""),
+        Backend::Ydotool => type_text(backend, key.text(), config),
+    }
+}
+
+fn run(command: &str, args: &[&str], input: &str) -> Result<()> {
+    debug!("Auto-typing with {} {:?}", command, args);
+    let mut process = commands::command(command)?
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(||format!("Failed to run {}", command))?;
+    let mut stdin = process.stdin.take().unwrap();
+    stdin.write_all(input.as_bytes())?;
+    drop(stdin);
+    let status = process.wait()?;
+    if !status.success() {
+        return Err(Error::msg(format!("{} failed with {}", command, status)))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, value: &str) -> FullField {
+        FullField { name: name.to_owned(), designation: name.to_owned(), value: value.to_owned(), section: None }
+    }
+
+    #[test]
+    fn keys_and_fields() {
+        let steps = parse("{username}{TAB}{password}{enter}").unwrap();
+        assert_eq!(steps, [
+            Step::Field("username".to_owned()),
+            Step::Key(Key::Tab),
+            Step::Field("password".to_owned()),
+            Step::Key(Key::Enter),
+        ]);
+    }
+
+    #[test]
+    fn text_between_fields_is_typed_as_is() {
+        let steps = parse("login {username}, then {SPACE}ok").unwrap();
+        assert_eq!(steps, [
+            Step::Text("login ".to_owned()),
+            Step::Field("username".to_owned()),
+            Step::Text(", then ".to_owned()),
+            Step::Key(Key::Space),
+            Step::Text("ok".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn delays() {
+        assert_eq!(parse("{DELAY 500}").unwrap(), [Step::Delay(500)]);
+        assert_eq!(parse("{ delay  20 }").unwrap(), [Step::Delay(20)]);
+        for bad in ["{DELAY}", "{DELAY }", "{DELAY soon}", "{DELAY -5}"] {
+            let error = parse(bad).unwrap_err();
+            assert!(error.to_string().contains("Bad delay"), "{}: {}", bad, error);
+        }
+    }
+
+    #[test]
+    fn malformed_sequences_fail() {
+        assert!(parse("{username}{TAB").unwrap_err().to_string().contains("Unclosed brace"));
+        assert!(parse("{username}{}").unwrap_err().to_string().contains("Empty placeholder"));
+    }
+
+    #[test]
+    fn unknown_placeholders_are_fields_that_must_exist() {
+        let steps = parse("{username}{otp}").unwrap();
+        assert_eq!(steps[1], Step::Field("otp".to_owned()));
+        // Nothing is typed when a field is missing
+        let error = type_sequence(&steps, &[field("username", "alice")], &AutoType::default()).unwrap_err();
+        assert_eq!(error.to_string(), "Field otp not found in item");
+    }
+}
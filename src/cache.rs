@@ -1,25 +1,41 @@
 use anyhow::{Context, Result, Error};
+use std::fs::{OpenOptions, Permissions};
 use std::path::{Path, PathBuf};
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::time::SystemTime;
+use log::warn;
 
 use crate::model::storage;
+use crate::op;
 use crate::model::op::OpConfig;
 
 const OP_CONFIG_PATH: &str = "~/.op/config";
 const CACHE_PATH: &str = "~/.config/r1pw/cache.json";
+/// Present while op can't reach 1Password
+const OFFLINE_PATH: &str = "~/.config/r1pw/offline";
+/// Touched whenever tokens are dropped, so running servers drop theirs too
+const LOCKED_PATH: &str = "~/.config/r1pw/locked";
+/// Bumped whenever items are listed with more details, so that accounts cached
+/// before are listed again rather than missing them:
+/// 1. vault UUIDs
+/// 2. vault names
+/// 3. categories
+/// 4. usernames of logins only
+pub const ITEMS_VERSION: u32 = 4;
 
 fn read_op_config() -> Result<OpConfig> {
     let path = shellexpand::full(OP_CONFIG_PATH)
         .with_context(||"Bad op config path")?;
     let path = Path::new(path.as_ref());
 
-    let config = std::fs::read_to_string(&path)?;
+    let config = std::fs::read_to_string(path)?;
     let config: OpConfig = serde_json::from_str(&config)?;
     Ok(config)
 }
 
 fn read_if_found(path: &Path) -> Result<Option<String>> {
-    match std::fs::read_to_string(&path) {
+    match std::fs::read_to_string(path) {
         Ok(c) => Ok(Some(c)),
         Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
         Err(e) => Err(e.into())
@@ -48,7 +64,8 @@ pub fn read() -> Result<storage::Cache> {
                 shorthand: i.shorthand,
                 email: i.email,
                 uuid: i.user_uuid,
-                items: vec![]
+                items: vec![],
+                items_version: ITEMS_VERSION,
             })
     ).collect();
     cache.accounts = accounts;
@@ -66,9 +83,99 @@ pub fn write(cache: &storage::Cache) -> Result<()> {
         .with_context(||"Error writing cache file")
 }
 
+/// Write a file only its owner can read, tightening an existing file's permissions first
+pub fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Error opening {:?} for writing", path))?;
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Error writing {:?}", path))
+}
+
+/// Forget the tokens of the accounts with these shorthands, or of every account.
+/// Items stay cached, returned are the accounts that had a token.
+pub fn drop_tokens(shorthands: Option<&[String]>) -> Result<Vec<storage::Account>> {
+    let mut dropped = vec![];
+    if let Some(c) = read_if_found(&get_cache_path()?)? {
+        let mut cache = serde_json::from_str::<storage::Cache>(&c)
+            .with_context(||"Error de-serialising cache file")?;
+        for account in cache.accounts.iter_mut()
+            .filter(|a| shorthands.is_none_or(|s| s.contains(&a.shorthand))) {
+            if account.token.is_some() {
+                dropped.push(account.clone());
+                account.token = None;
+            }
+        }
+        write(&cache)?;
+    }
+    let path = expand(LOCKED_PATH)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Error ensuring path {:?} exists", parent))?;
+    }
+    std::fs::write(&path, "")
+        .with_context(||"Error writing lock marker")?;
+    Ok(dropped)
+}
+
+/// Whether tokens were dropped at or after the given time, as the marker's mtime may be coarser than it
+pub fn locked_since(time: SystemTime) -> bool {
+    expand(LOCKED_PATH).ok()
+        .and_then(|path| path.metadata().ok())
+        .and_then(|m| m.modified().ok())
+        .is_some_and(|modified| modified >= time)
+}
+
 fn get_cache_path() -> Result<PathBuf> {
     let path = shellexpand::full(CACHE_PATH)
         .with_context(|| format!("Cache file path {} is invalid", CACHE_PATH))?;
     let path = Path::new(path.as_ref());
     Ok(path.to_owned())
 }
+
+/// Remember whether op last failed to reach 1Password, so the item list can say so
+pub fn set_offline(offline: bool) -> Result<()> {
+    let path = expand(OFFLINE_PATH)?;
+    if offline {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Error ensuring path {:?} exists", parent))?;
+        }
+        std::fs::write(&path, "")
+            .with_context(||"Error writing offline marker")
+    } else {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Note from the outcome of an op command whether it reached 1Password, passing the outcome on.
+/// A timed out command says neither way.
+pub fn note_offline<T>(result: Result<T>) -> Result<T> {
+    let offline = match &result {
+        Err(e) if e.is::<op::Timeout>() => return result,
+        Err(e) => e.is::<op::Offline>(),
+        Ok(_) => false,
+    };
+    if let Err(e) = set_offline(offline) {
+        warn!("Unable to note whether op is offline: {:#}", e);
+    }
+    result
+}
+
+pub fn is_offline() -> bool {
+    expand(OFFLINE_PATH).is_ok_and(|path| path.exists())
+}
+
+fn expand(path: &str) -> Result<PathBuf> {
+    let expanded = shellexpand::full(path)
+        .with_context(|| format!("Path {} is invalid", path))?;
+    Ok(Path::new(expanded.as_ref()).to_owned())
+}
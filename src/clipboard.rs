@@ -1,13 +1,43 @@
-use std::process::{Command, Stdio};
+use std::process::Stdio;
 use std::io::Write;
+use anyhow::{Result, Error, Context};
 
-pub fn copy_to_clipboard(value: &str) {
-    let mut copy = Command::new("xsel")
+use crate::commands;
+
+pub fn copy_to_clipboard(value: &str) -> Result<()> {
+    let mut copy = commands::command("xsel")?
         .arg("-b")
         .stdin(Stdio::piped())
-        .spawn().unwrap();
+        .spawn()
+        .with_context(||"Failed to run xsel")?;
     let mut stdin = copy.stdin.take().unwrap();
-    stdin.write_all(value.as_bytes()).unwrap();
+    stdin.write_all(value.as_bytes())?;
     drop(stdin);
-    copy.wait().unwrap();
+    let status = copy.wait()?;
+    if !status.success() {
+        return Err(Error::msg(format!("xsel failed with {}", status)))
+    }
+    Ok(())
+}
+
+/// OSC 52 escape sequence asking the terminal to set its clipboard, which works
+/// over ssh and on consoles where there is no X server for xsel
+pub fn osc52(value: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(value.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
 }
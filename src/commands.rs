@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use log::debug;
+
+use crate::model::config::Tool;
+
+static TOOLS: OnceLock<HashMap<String, Tool>> = OnceLock::new();
+
+/// Use the configured paths and arguments for the rest of the run
+pub fn init(tools: HashMap<String, Tool>) {
+    let _ = TOOLS.set(tools);
+}
+
+/// Executable and extra arguments for an external tool such as `op`.
+///
+/// `R1PW_OP` and `R1PW_OP_ARGS` (split on whitespace) replace the configured
+/// path and arguments, which default to plain `op` and none.
+fn resolve(name: &str) -> (String, Vec<String>) {
+    let variable = format!("R1PW_{}", name.to_uppercase());
+    let tool = TOOLS.get().and_then(|tools| tools.get(name));
+    let path = env::var(&variable).ok()
+        .or_else(|| tool.and_then(|t| t.path.clone()))
+        .map(|path| shellexpand::tilde(&path).into_owned())
+        .unwrap_or_else(|| name.to_owned());
+    let args = match env::var(format!("{}_ARGS", variable)) {
+        Ok(args) => args.split_whitespace().map(|a| a.to_owned()).collect(),
+        Err(_) => tool.map(|t| t.args.clone()).unwrap_or_default(),
+    };
+    (path, args)
+}
+
+/// Command running a tool, failing clearly when it isn't installed where expected
+pub fn command(name: &str) -> io::Result<Command> {
+    let (path, args) = resolve(name);
+    let program = if path.contains('/') {
+        Some(PathBuf::from(&path)).filter(|p| is_executable(p))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found at {}", name, path)))?
+    } else {
+        find(&path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found on PATH", path)))?
+    };
+    debug!("Using {:?} {:?} for {}", program, args, name);
+    let mut command = Command::new(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// Whether a tool would be found, for choosing between alternatives
+pub fn exists(name: &str) -> bool {
+    let (path, _) = resolve(name);
+    if path.contains('/') {
+        is_executable(Path::new(&path))
+    } else {
+        find(&path).is_some()
+    }
+}
+
+fn find(program: &str) -> Option<PathBuf> {
+    env::var_os("PATH").and_then(|paths| env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path)))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
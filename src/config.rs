@@ -0,0 +1,21 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::io::ErrorKind;
+
+use crate::model::config::Config;
+
+const CONFIG_PATH: &str = "~/.config/r1pw/config.json";
+
+// Missing config file is the same as an empty one
+pub fn read() -> Result<Config> {
+    let path = shellexpand::full(CONFIG_PATH)
+        .with_context(|| format!("Config file path {} is invalid", CONFIG_PATH))?;
+    let path = Path::new(path.as_ref());
+
+    match std::fs::read_to_string(path) {
+        Ok(c) => serde_json::from_str(&c)
+            .with_context(|| format!("Error de-serialising config file {:?}", path)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,19 +1,38 @@
-use std::process::{Command, Stdio};
+use std::process::Stdio;
 use std::io::Write;
 use std::io;
 use log::warn;
 use anyhow::Result;
+use itertools::Itertools;
 
-pub fn select<F: FnOnce() -> Result<()>>(input: &str, while_waiting_function: F) -> io::Result<Option<String>> {
-    dmenu(input, ["-b", "-i", "-l", "20"].to_vec(), while_waiting_function)
-}
+use crate::clipboard;
+use crate::commands;
+use crate::launcher::{Launcher, Menu, Choice};
+
+pub struct Dmenu;
+
+impl Launcher for Dmenu {
+    // dmenu has nowhere to show previews, and no keys for shortcuts
+    fn select(&self, menu: &Menu, while_waiting: Box<dyn FnOnce() -> Result<()> + '_>) -> io::Result<Option<Choice>> {
+        let input = menu.entries.iter().map(|e| e.line.as_str()).join("\n");
+        let mut args = ["-b", "-i", "-l", "20"].to_vec();
+        if let Some(prompt) = menu.prompt {
+            args.extend(&["-p", prompt]);
+        }
+        Ok(dmenu(&input, args, while_waiting)?.map(Choice::Line))
+    }
 
-pub fn prompt_hidden(prompt: &str) -> io::Result<Option<String>> {
-    dmenu("", ["-b", "-p", prompt, "-nb", "black", "-nf", "black"].to_vec(), ||Ok(()))
+    fn prompt_hidden(&self, prompt: &str) -> io::Result<Option<String>> {
+        dmenu("", ["-b", "-p", prompt, "-nb", "black", "-nf", "black"].to_vec(), ||Ok(()))
+    }
+
+    fn copy(&self, value: &str) -> Result<()> {
+        clipboard::copy_to_clipboard(value)
+    }
 }
 
 fn dmenu<F: FnOnce() -> Result<()>>(input: &str, args: Vec<&str>, while_waiting_function: F) -> io::Result<Option<String>> {
-    let mut dmenu = Command::new("dmenu")
+    let mut dmenu = commands::command("dmenu")?
         .args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
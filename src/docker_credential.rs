@@ -0,0 +1,49 @@
+use serde::{Serialize, Deserialize};
+
+use crate::model::logical;
+use crate::url_match;
+
+/// Docker only treats this exact message as a missing credential
+pub const NOT_FOUND: &str = "credentials not found in native keychain";
+
+/// Items tagged `docker` are preferred when several share a registry host
+pub const TAG: &str = "docker";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Credentials {
+    #[serde(rename = "ServerURL")]
+    pub server_url: String,
+    pub username: String,
+    pub secret: String,
+}
+
+/// Names Docker Hub goes by, docker itself asks for `https://index.docker.io/v1/`
+const DOCKER_HUB: &[&str] = &["index.docker.io", "docker.io", "registry-1.docker.io"];
+
+/// Registry host of a server URL, with every Docker Hub alias as `index.docker.io`
+pub fn registry_host(url: &str) -> Option<String> {
+    url_match::host(url).map(|host| {
+        if DOCKER_HUB.contains(&host.as_str()) {
+            DOCKER_HUB[0].to_owned()
+        } else {
+            host
+        }
+    })
+}
+
+/// Registry host an item is explicitly tagged for with `docker:<host>`
+pub fn tagged_host(item: &logical::Item) -> Option<String> {
+    item.tags.iter()
+        .find_map(|tag| tag.strip_prefix("docker:"))
+        .and_then(registry_host)
+}
+
+pub fn is_tagged(item: &logical::Item) -> bool {
+    item.tags.iter().any(|tag| tag == TAG) || tagged_host(item).is_some()
+}
+
+/// Registry an item holds credentials for, as reported by `list`
+pub fn server(item: &logical::Item) -> Option<String> {
+    tagged_host(item).or_else(|| item.url.clone())
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use itertools::Itertools;
+
+use crate::model::config::Vaults;
+use crate::model::logical;
+
+/// Narrows the item list down by tag, account and vault.
+///
+/// Every tag must be present on an item, while accounts and vaults match
+/// if the item belongs to any of them. Vaults are given by name or UUID.
+/// Comparisons ignore case.
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    pub tags: Vec<String>,
+    pub accounts: Vec<String>,
+    pub vaults: Vec<String>,
+    /// Filters combined with this one by `and`, which must all match as well
+    narrowing: Vec<Filter>,
+}
+
+impl Filter {
+    pub fn new(tags: Vec<String>, accounts: Vec<String>, vaults: Vec<String>) -> Filter {
+        Filter { tags, accounts, vaults, narrowing: vec![] }
+    }
+
+    /// Parse launcher input such as `#work @personal ~Shared`.
+    ///
+    /// Returns None unless every word is a `#tag`, `@account` or `~vault`,
+    /// so that ordinary search text is never mistaken for a filter.
+    pub fn parse(query: &str) -> Option<Filter> {
+        let mut filter = Filter::default();
+        for word in query.split_whitespace() {
+            if let Some(tag) = word.strip_prefix('#').filter(|t| !t.is_empty()) {
+                filter.tags.push(tag.to_owned());
+            } else if let Some(account) = word.strip_prefix('@').filter(|a| !a.is_empty()) {
+                filter.accounts.push(account.to_owned());
+            } else if let Some(vault) = word.strip_prefix('~').filter(|v| !v.is_empty()) {
+                filter.vaults.push(vault.to_owned());
+            } else {
+                return None
+            }
+        }
+        if filter.is_empty() {
+            return None
+        }
+        Some(filter)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.accounts.is_empty() && self.vaults.is_empty()
+            && self.narrowing.iter().all(Filter::is_empty)
+    }
+
+    /// Whether any vault was asked for, here or in a combined filter
+    pub fn names_vaults(&self) -> bool {
+        !self.vaults.is_empty() || self.narrowing.iter().any(Filter::names_vaults)
+    }
+
+    /// Combine with another filter, narrowing further. `@a` and then `@b`
+    /// leaves only items in both accounts, which is none.
+    pub fn and(mut self, other: Filter) -> Filter {
+        self.narrowing.push(other);
+        self
+    }
+
+    pub fn matches(&self, item: &logical::Item) -> bool {
+        let tags = self.tags.iter()
+            .all(|tag| item.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+        let account = self.accounts.is_empty() || self.accounts.iter()
+            .any(|a| a.eq_ignore_ascii_case(&item.account_name));
+        let vault = self.vaults.is_empty() || self.vaults.iter().any(|v| in_vault(v, item));
+        tags && account && vault && self.narrowing.iter().all(|f| f.matches(item))
+    }
+}
+
+/// As given, e.g. `tag work, vault Private or Shared`
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let either = |kind: &str, names: &[String]| Some(names)
+            .filter(|n| !n.is_empty())
+            .map(|n| format!("{} {}", kind, n.join(" or ")));
+        let terms = self.tags.iter().map(|t| format!("tag {}", t))
+            .chain(either("account", &self.accounts))
+            .chain(either("vault", &self.vaults))
+            .chain(self.narrowing.iter().filter(|n| !n.is_empty()).map(|n| n.to_string()));
+        write!(f, "{}", terms.format(", "))
+    }
+}
+
+/// Whether the item is in the vault with this name or UUID
+pub fn in_vault(vault: &str, item: &logical::Item) -> bool {
+    [&item.vault_uuid, &item.vault_name].iter()
+        .any(|v| v.as_ref().is_some_and(|v| v.eq_ignore_ascii_case(vault)))
+}
+
+/// Whether the configured vault rules let the item be listed
+pub fn vault_allowed(rules: &Vaults, item: &logical::Item) -> bool {
+    let included = rules.include.is_empty() || rules.include.iter().any(|v| in_vault(v, item));
+    let excluded = rules.exclude.iter().any(|v| in_vault(v, item));
+    included && !excluded
+}
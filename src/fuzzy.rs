@@ -0,0 +1,167 @@
+use crate::history;
+use crate::model::logical;
+use crate::model::storage::History;
+use crate::url_match;
+
+const MATCH: i64 = 16;
+/// First character of the text
+const START: i64 = 12;
+/// First character of a word, after a space or punctuation
+const BOUNDARY: i64 = 10;
+/// Capital letter after a lower case one, as in Git*H*ub
+const CAMEL: i64 = 8;
+const CONSECUTIVE: i64 = 4;
+const MAX_GAP_PENALTY: i64 = 10;
+/// Extra for every other field a word also matches
+const OTHER_FIELD: i64 = 4;
+
+/// Score the best way for the characters of `query` to appear in `text` in order, ignoring case.
+///
+/// None when they don't all appear.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let query = query.to_lowercase().chars().collect::<Vec<_>>();
+    let original = text.chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    // Lower casing can change the length of some scripts, where bonuses are skipped
+    let bonuses = original.len() == text.len();
+    if query.is_empty() {
+        return Some(0)
+    }
+
+    let bonus = |j: usize| -> i64 {
+        if !bonuses {
+            0
+        } else if j == 0 {
+            START
+        } else if !original[j - 1].is_alphanumeric() {
+            BOUNDARY
+        } else if original[j - 1].is_lowercase() && original[j].is_uppercase() {
+            CAMEL
+        } else {
+            0
+        }
+    };
+
+    // best[j]: best score with the query so far matched, its last character at j
+    let mut best: Vec<Option<i64>> = text.iter().enumerate()
+        .map(|(j, &c)| if c == query[0] { Some(MATCH + bonus(j)) } else { None })
+        .collect();
+    for &q in &query[1..] {
+        let mut next = vec![None; text.len()];
+        for (j, &c) in text.iter().enumerate() {
+            if c != q {
+                continue
+            }
+            next[j] = (0..j)
+                .filter_map(|k| best[k].map(|s| s + if k + 1 == j {
+                    CONSECUTIVE
+                } else {
+                    -((j - k - 1) as i64).min(MAX_GAP_PENALTY)
+                }))
+                .max()
+                .map(|s| s + MATCH + bonus(j));
+        }
+        best = next;
+    }
+    best.into_iter().flatten().max()
+}
+
+/// Score an item against every word of the query, each matching its name, URL host, tags,
+/// username or account. Names count the most.
+pub fn score_item(query: &str, item: &logical::Item) -> Option<i64> {
+    let host = item.url.as_deref().and_then(url_match::host);
+    let mut fields: Vec<(&str, i64)> = vec![(&item.name, 10)];
+    if let Some(host) = &host {
+        fields.push((host, 9));
+    }
+    if let Some(username) = &item.username {
+        fields.push((username, 8));
+    }
+    fields.extend(item.tags.iter().map(|t| (t.as_str(), 8)));
+    fields.push((&item.account_name, 6));
+    if let Some(vault) = &item.vault_name {
+        fields.push((vault, 6));
+    }
+
+    query.split_whitespace().map(|word| {
+        let scores = fields.iter()
+            .filter_map(|(text, weight)| score(word, text).map(|s| s * weight / 10))
+            .collect::<Vec<_>>();
+        let best = scores.iter().max()?;
+        Some(best + OTHER_FIELD * (scores.len() as i64 - 1))
+    }).sum()
+}
+
+/// Items matching the query, best first, often and recently used ones boosted
+pub fn rank<'a>(items: &[&'a logical::Item], query: &str, history: &History) -> Vec<(&'a logical::Item, i64)> {
+    let mut ranked = items.iter()
+        .filter_map(|&item| score_item(query, item).map(|s| (item, s + history::frecency(history, &item.uuid))))
+        .collect::<Vec<_>>();
+    // Shorter names are closer matches for the same score
+    ranked.sort_by_key(|(item, score)| (-score, item.name.len()));
+    ranked
+}
+
+/// Often and recently used items first, otherwise keeping the order
+pub fn by_frecency<'a>(items: &[&'a logical::Item], history: &History) -> Vec<&'a logical::Item> {
+    let mut items = items.to_vec();
+    items.sort_by_key(|item| -history::frecency(history, &item.uuid));
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::logical::Item;
+    use crate::model::storage::Usage;
+
+    fn names(ranked: &[(&Item, i64)]) -> Vec<String> {
+        ranked.iter().map(|(i, _)| i.name.clone()).collect()
+    }
+
+    #[test]
+    fn score_needs_every_character_in_order() {
+        assert!(score("gh", "GitHub").is_some());
+        assert_eq!(score("hg", "GitHub"), None);
+        assert_eq!(score("", "GitHub"), Some(0));
+    }
+
+    #[test]
+    fn score_prefers_word_starts_and_runs() {
+        assert!(score("gh", "GitHub") > score("gh", "Lighthouse"));
+        assert!(score("hub", "GitHub") > score("hub", "Shrub"));
+    }
+
+    #[test]
+    fn score_item_finds_github_first_for_gh() {
+        let items = [Item::named("Lighthouse", None), Item::named("Jenkins", None), Item::named("GitHub", None)];
+        let items = items.iter().collect::<Vec<_>>();
+        let ranked = rank(&items, "gh", &History::default());
+        assert_eq!(names(&ranked), vec!["GitHub", "Lighthouse"]);
+    }
+
+    #[test]
+    fn score_item_matches_the_url_host_and_needs_every_word() {
+        let mut item = Item::named("Work login", Some("https://sso.example.com"));
+        item.username = Some("alice".to_owned());
+        assert!(score_item("sso", &item).is_some());
+        assert!(score_item("work alice", &item).is_some());
+        assert_eq!(score_item("work bob", &item), None);
+        // Names count more than usernames
+        let mut alice = Item::named("Other", None);
+        alice.username = Some("alice".to_owned());
+        assert!(score_item("alice", &Item::named("Alice", None)) > score_item("alice", &alice));
+    }
+
+    #[test]
+    fn rank_boosts_often_and_recently_used_items() {
+        let items = [Item::named("GitHub", None), Item::named("GitLab", None)];
+        let items = items.iter().collect::<Vec<_>>();
+        assert_eq!(names(&rank(&items, "git", &History::default())), vec!["GitHub", "GitLab"]);
+
+        let mut history = History::default();
+        history.items.insert("gitlab".to_owned(), Usage { count: 3, last_used: history::now() });
+        assert_eq!(names(&rank(&items, "git", &history)), vec!["GitLab", "GitHub"]);
+        assert_eq!(by_frecency(&items, &history).iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["GitLab", "GitHub"]);
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Result, Error};
+use itertools::Itertools;
+
+use crate::model::config::{Generate, Recipe};
+
+/// EFF's large diceware list, from https://www.eff.org/dice
+const WORDLIST: &str = include_str!("eff_large_wordlist.txt");
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!@#$%^&*()-_=+[]{};:,.<>/?~";
+const AMBIGUOUS: &str = "0Oo1Il|";
+const DEFAULT_LENGTH: usize = 24;
+const DEFAULT_SEPARATOR: &str = "-";
+
+/// A made up password, and how hard it is to guess
+#[derive(Debug)]
+pub struct Generated {
+    pub value: String,
+    /// Bits of entropy, assuming the recipe and its rules are known to an attacker
+    pub entropy: f64,
+    pub description: String,
+}
+
+/// Presets that come with r1pw
+fn builtin(name: &str) -> Option<Recipe> {
+    match name {
+        // Within IAM's default policy, with only the symbols it accepts
+        "aws" => Some(Recipe {
+            length: Some(32),
+            symbol_set: Some("!@#$%^&*()_+-=[]{}|'".to_owned()),
+            ..Recipe::default()
+        }),
+        "pin6" => Some(Recipe {
+            length: Some(6),
+            lowercase: Some(false),
+            uppercase: Some(false),
+            symbols: Some(false),
+            ..Recipe::default()
+        }),
+        // For systems that reject symbols, easy to read out or type by hand
+        "legacy-16" => Some(Recipe {
+            length: Some(16),
+            symbols: Some(false),
+            exclude_ambiguous: Some(true),
+            ..Recipe::default()
+        }),
+        _ => None,
+    }
+}
+
+/// Whether the recipe says anything about characters, only passwords have them
+fn has_character_settings(recipe: &Recipe) -> bool {
+    recipe.length.is_some() || recipe.lowercase.is_some() || recipe.uppercase.is_some() || recipe.digits.is_some()
+        || recipe.symbols.is_some() || recipe.symbol_set.is_some() || recipe.exclude_ambiguous.is_some()
+}
+
+/// Settings given take precedence over the preset's, but can't bring back what it leaves out
+pub fn recipe(config: &Generate, name: Option<&str>, given: Recipe) -> Result<Recipe> {
+    let preset = match name {
+        None => Recipe::default(),
+        Some(name) => config.presets.get(name).cloned().or_else(|| builtin(name))
+            .ok_or_else(|| Error::msg(format!(
+                "No preset {}, choose from {}", name,
+                ["aws", "pin6", "legacy-16"].iter().copied()
+                    .chain(config.presets.keys().map(|k| k.as_str()))
+                    .unique().sorted().join(", ")
+            )))?,
+    };
+    let preset_name = format!("Preset {}", name.unwrap_or_default());
+    let conflict = if given.words.is_some() && has_character_settings(&preset) {
+        Some(format!("--words makes a passphrase, but preset {} makes a password", name.unwrap_or_default()))
+    } else if given.words.or(preset.words).is_some() && has_character_settings(&given) {
+        let source = if given.words.is_some() { "--words" } else { &preset_name };
+        Some(format!("{} makes a passphrase, --length and the character options only apply to passwords", source))
+    } else if given.symbol_set.is_some() && preset.symbols == Some(false) {
+        Some(format!("{} leaves out symbols, --symbols can't add them back", preset_name))
+    } else if given.separator.is_some() && given.words.or(preset.words).is_none() {
+        Some("--separator only applies to passphrases".to_owned())
+    } else {
+        None
+    };
+    if let Some(conflict) = conflict {
+        return Err(Error::msg(conflict))
+    }
+    Ok(Recipe {
+        length: given.length.or(preset.length),
+        words: given.words.or(preset.words),
+        separator: given.separator.or(preset.separator),
+        lowercase: given.lowercase.or(preset.lowercase),
+        uppercase: given.uppercase.or(preset.uppercase),
+        digits: given.digits.or(preset.digits),
+        symbols: given.symbols.or(preset.symbols),
+        symbol_set: given.symbol_set.or(preset.symbol_set),
+        exclude_ambiguous: given.exclude_ambiguous.or(preset.exclude_ambiguous),
+    })
+}
+
+pub fn generate(recipe: &Recipe) -> Result<Generated> {
+    match recipe.words {
+        Some(words) => passphrase(words, recipe.separator.as_deref().unwrap_or(DEFAULT_SEPARATOR)),
+        None => password(recipe),
+    }
+}
+
+fn passphrase(count: usize, separator: &str) -> Result<Generated> {
+    if count == 0 {
+        return Err(Error::msg("A passphrase needs at least one word"))
+    }
+    let words = WORDLIST.lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .collect::<Vec<_>>();
+    let value = (0..count)
+        .map(|_| random_below(words.len()).map(|i| words[i]))
+        .collect::<Result<Vec<_>>>()?
+        .join(separator);
+    Ok(Generated {
+        value,
+        entropy: count as f64 * (words.len() as f64).log2(),
+        description: format!("a {} word passphrase", count),
+    })
+}
+
+fn password(recipe: &Recipe) -> Result<Generated> {
+    let length = recipe.length.unwrap_or(DEFAULT_LENGTH);
+    let exclude_ambiguous = recipe.exclude_ambiguous.unwrap_or(false);
+    let classes = [
+        (recipe.lowercase, LOWERCASE),
+        (recipe.uppercase, UPPERCASE),
+        (recipe.digits, DIGITS),
+        (recipe.symbols, recipe.symbol_set.as_deref().unwrap_or(SYMBOLS)),
+    ].iter()
+        .filter(|(enabled, _)| enabled.unwrap_or(true))
+        .map(|(_, characters)| characters.chars()
+            .filter(|c| !exclude_ambiguous || !AMBIGUOUS.contains(*c))
+            .unique()
+            .collect::<Vec<_>>())
+        .filter(|characters| !characters.is_empty())
+        .collect::<Vec<_>>();
+    if classes.is_empty() {
+        return Err(Error::msg("No characters left to make a password from"))
+    }
+    if length < classes.len() {
+        return Err(Error::msg(format!("A password with {} kinds of characters needs at least {} characters", classes.len(), classes.len())))
+    }
+    let characters = classes.iter().flatten().copied().unique().collect::<Vec<_>>();
+    // Sites often require one of each kind, try again until there is
+    let value = loop {
+        let value = (0..length)
+            .map(|_| random_below(characters.len()).map(|i| characters[i]))
+            .collect::<Result<String>>()?;
+        if classes.iter().all(|class| value.chars().any(|c| class.contains(&c))) {
+            break value
+        }
+    };
+    Ok(Generated {
+        value,
+        entropy: password_entropy(&classes, &characters, length),
+        description: format!("a {} character password", length),
+    })
+}
+
+/// Bits of entropy of a password with at least one character of each class.
+///
+/// Counts the passwords left by inclusion-exclusion over the classes missing from them,
+/// relative to all passwords of the characters, so large lengths don't overflow.
+fn password_entropy(classes: &[Vec<char>], characters: &[char], length: usize) -> f64 {
+    let share = (0..=classes.len())
+        .flat_map(|k| classes.iter().combinations(k))
+        .map(|missing| {
+            let left = characters.iter().filter(|c| missing.iter().all(|class| !class.contains(c))).count();
+            let share = (left as f64 / characters.len() as f64).powi(length as i32);
+            if missing.len() % 2 == 0 { share } else { -share }
+        })
+        .sum::<f64>();
+    length as f64 * (characters.len() as f64).log2() + share.log2()
+}
+
+/// Uniformly random number below `n`
+fn random_below(n: usize) -> Result<usize> {
+    let range = 1u64 << 32;
+    // Values past the last whole multiple of n would favour the low numbers
+    let limit = range - range % n as u64;
+    loop {
+        let mut bytes = [0u8; 4];
+        getrandom::getrandom(&mut bytes).map_err(|e| Error::msg(format!("Error generating random numbers: {}", e)))?;
+        let value = u32::from_ne_bytes(bytes) as u64;
+        if value < limit {
+            return Ok((value % n as u64) as usize)
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::BufRead;
+use anyhow::Result;
+
+/// Attributes git passes to credential helpers, see `git help credential`
+#[derive(Debug, Default)]
+pub struct Request {
+    pub protocol: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub username: Option<String>,
+    pub url: Option<String>,
+}
+
+impl Request {
+    /// URL being authenticated, as given or put together from its parts
+    pub fn url(&self) -> Option<String> {
+        if let Some(url) = &self.url {
+            return Some(url.clone())
+        }
+        let host = self.host.as_ref()?;
+        Some(format!(
+            "{}://{}/{}",
+            self.protocol.as_deref().unwrap_or("https"),
+            host,
+            self.path.as_deref().unwrap_or(""),
+        ))
+    }
+}
+
+/// Read `key=value` lines up to a blank line or the end of input
+pub fn read<R: BufRead>(input: R) -> Result<Request> {
+    let mut request = Request::default();
+    for line in input.lines() {
+        let line = line?;
+        if line.is_empty() {
+            break
+        }
+        let (key, value) = match line.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let value = Some(value.to_owned());
+        match key {
+            "protocol" => request.protocol = value,
+            "host" => request.host = value,
+            "path" => request.path = value,
+            "username" => request.username = value,
+            "url" => request.url = value,
+            _ => {},
+        }
+    }
+    Ok(request)
+}
+
+pub fn format(username: &str, password: &str) -> String {
+    format!("username={}\npassword={}\n", username, password)
+}
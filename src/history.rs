@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::io::ErrorKind;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::model::storage::History;
+
+const HISTORY_PATH: &str = "~/.config/r1pw/history.json";
+const DAY: u64 = 24 * 60 * 60;
+/// Uses beyond this don't make an item any more frequent
+const MAX_COUNT: u32 = 10;
+
+// Missing history is the same as an empty one
+pub fn read() -> Result<History> {
+    let path = get_history_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(c) => serde_json::from_str(&c)
+            .with_context(|| format!("Error de-serialising history file {:?}", path)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(History::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Note that an item was chosen just now
+pub fn record(uuid: &str) -> Result<()> {
+    let mut history = read()?;
+    let usage = history.items.entry(uuid.to_owned()).or_default();
+    usage.count = usage.count.saturating_add(1);
+    usage.last_used = now();
+
+    let path = get_history_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Error ensuring path {:?} exists", parent))?;
+    }
+    std::fs::write(&path, serde_json::to_string(&history)?)
+        .with_context(||"Error writing history file")
+}
+
+/// Boost for often and recently used items, from 0 up to 100
+pub fn frecency(history: &History, uuid: &str) -> i64 {
+    let usage = match history.items.get(uuid) {
+        Some(usage) => usage,
+        None => return 0,
+    };
+    let age = now().saturating_sub(usage.last_used) / DAY;
+    let recency = match age {
+        0..=3 => 100,
+        4..=14 => 70,
+        15..=31 => 50,
+        32..=90 => 30,
+        _ => 10,
+    };
+    usage.count.min(MAX_COUNT) as i64 * recency / MAX_COUNT as i64
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn get_history_path() -> Result<PathBuf> {
+    let path = shellexpand::full(HISTORY_PATH)
+        .with_context(|| format!("History file path {} is invalid", HISTORY_PATH))?;
+    Ok(Path::new(path.as_ref()).to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::storage::Usage;
+
+    fn used(count: u32, days_ago: u64) -> History {
+        let mut history = History::default();
+        history.items.insert("i1".to_owned(), Usage { count, last_used: now() - days_ago * DAY });
+        history
+    }
+
+    #[test]
+    fn frecency_grows_with_use_up_to_a_limit() {
+        assert_eq!(frecency(&History::default(), "i1"), 0);
+        assert_eq!(frecency(&used(1, 0), "i1"), 10);
+        assert_eq!(frecency(&used(10, 0), "i1"), 100);
+        assert_eq!(frecency(&used(50, 0), "i1"), 100);
+    }
+
+    #[test]
+    fn frecency_fades_with_age() {
+        assert_eq!(frecency(&used(10, 10), "i1"), 70);
+        assert_eq!(frecency(&used(10, 20), "i1"), 50);
+        assert_eq!(frecency(&used(10, 60), "i1"), 30);
+        assert_eq!(frecency(&used(10, 400), "i1"), 10);
+        assert_eq!(frecency(&used(5, 20), "i1"), 25);
+    }
+}
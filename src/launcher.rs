@@ -0,0 +1,66 @@
+use std::env;
+use std::io;
+use std::sync::OnceLock;
+use anyhow::Result;
+
+use crate::dmenu::Dmenu;
+use crate::model::config::LauncherKind;
+use crate::terminal::Terminal;
+
+/// Field copied straight from the item list, skipping the field choice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shortcut {
+    Username,
+    Password,
+    Totp,
+}
+
+/// Line shown in the launcher
+#[derive(Debug)]
+pub struct Entry {
+    pub line: String,
+    /// Non-secret details shown beside the list, where the launcher can
+    pub preview: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Menu<'a> {
+    pub entries: &'a [Entry],
+    pub prompt: Option<&'a str>,
+    /// Offer the copy shortcuts, only lists of items have fields to copy
+    pub shortcuts: bool,
+}
+
+#[derive(Debug)]
+pub enum Choice {
+    /// Chosen line, or the text typed when it matches none
+    Line(String),
+    /// Line whose field was asked for with a shortcut
+    Shortcut(String, Shortcut),
+}
+
+pub trait Launcher {
+    /// Show the menu, running `while_waiting` while the user chooses. None when cancelled.
+    fn select(&self, menu: &Menu, while_waiting: Box<dyn FnOnce() -> Result<()> + '_>) -> io::Result<Option<Choice>>;
+    /// Ask for a secret without showing it. None when cancelled.
+    fn prompt_hidden(&self, prompt: &str) -> io::Result<Option<String>>;
+    /// Put a value on the clipboard the user of this launcher can paste from
+    fn copy(&self, value: &str) -> Result<()>;
+}
+
+static KIND: OnceLock<LauncherKind> = OnceLock::new();
+
+/// Choose the launcher for the rest of the run
+pub fn init(kind: LauncherKind) {
+    let _ = KIND.set(kind);
+}
+
+pub fn current() -> &'static dyn Launcher {
+    match KIND.get().copied().unwrap_or(LauncherKind::Auto) {
+        LauncherKind::Dmenu => &Dmenu,
+        LauncherKind::Terminal => &Terminal,
+        // dmenu needs a display, without one (ssh, a console) the terminal is all there is
+        LauncherKind::Auto if env::var_os("DISPLAY").is_some() || env::var_os("WAYLAND_DISPLAY").is_some() => &Dmenu,
+        LauncherKind::Auto => &Terminal,
+    }
+}
@@ -1,30 +1,276 @@
 mod model;
 mod cache;
+mod config;
+mod autotype;
 mod clipboard;
+mod commands;
 mod op;
 mod dmenu;
+mod launcher;
+mod terminal;
+mod filter;
+mod fuzzy;
+mod history;
+mod url_match;
+mod window;
+mod git_credential;
+mod docker_credential;
+mod reference;
+mod run;
+mod template;
+mod askpass;
+mod pinentry;
+mod secret_service;
+mod native_host;
+mod value_cache;
+mod session;
+mod watch;
+mod generate;
 
 use model::logical;
 use model::logical::*;
 use model::storage;
+use model::config::{Config, LauncherKind, Recipe};
+use model::output;
+use filter::Filter;
+
+use std::io;
+use std::io::Read;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
 
 use log::*;
 use itertools::Itertools;
 use anyhow::{Result, Context, Error};
+use structopt::StructOpt;
+use structopt::clap::AppSettings;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Pick 1Password items with dmenu")]
+struct Opt {
+    /// Only list items with this tag, may be repeated
+    #[structopt(long = "tag", number_of_values = 1, global = true)]
+    tags: Vec<String>,
+    /// Only list items from this account shorthand, may be repeated
+    #[structopt(long = "account", number_of_values = 1, global = true)]
+    accounts: Vec<String>,
+    /// Only list items from this vault, by name or UUID, may be repeated
+    #[structopt(long = "vault", number_of_values = 1, global = true)]
+    vaults: Vec<String>,
+    /// Type the selected field into the focused window instead of copying it
+    #[structopt(long = "type")]
+    autotype: bool,
+    /// Type this sequence instead of a single field, e.g. `{username}{TAB}{password}{ENTER}`
+    #[structopt(long)]
+    sequence: Option<String>,
+    /// Seconds to wait for any op command before giving up, instead of 30 (120 to sign in)
+    #[structopt(long)]
+    timeout: Option<u64>,
+    /// Launcher for menus and prompts: auto, dmenu or terminal
+    #[structopt(long)]
+    launcher: Option<LauncherKind>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Pick an item by URL, ranking items for the same site first
+    Match {
+        /// URL or host name of the site to log in to
+        url: Option<String>,
+        /// Read the URL from the title of the focused window instead
+        #[structopt(long)]
+        window: bool,
+    },
+    /// Act as a git credential helper, see `git help credential`
+    #[structopt(name = "git-credential")]
+    GitCredential {
+        #[structopt(possible_values = &["get", "store", "erase"])]
+        action: String,
+    },
+    /// Act as a docker credential helper, see `docker login --help`
+    #[structopt(name = "docker-credential")]
+    DockerCredential {
+        #[structopt(possible_values = &["get", "store", "erase", "list"])]
+        action: String,
+    },
+    /// Run a command with secrets in its environment
+    Run {
+        /// Set a variable to an item's field, as NAME=<item>/<field>, may be repeated
+        #[structopt(long = "env", number_of_values = 1)]
+        env: Vec<String>,
+        /// Read NAME=<item>/<field> lines from a file, may be repeated
+        #[structopt(long = "env-file", number_of_values = 1, parse(from_os_str))]
+        env_files: Vec<PathBuf>,
+        /// Hide the secret values in the command's output
+        #[structopt(long)]
+        mask: bool,
+        /// Command to run and its arguments, after `--`
+        #[structopt(required = true)]
+        args: Vec<String>,
+    },
+    /// Render a template, replacing `{{ r1pw://<account>/<item>/<field> }}` with values
+    Inject {
+        /// Template to read
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+        /// File to write, readable only by its owner, stdout if not given
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Answer an ssh or sudo password prompt, for use as SSH_ASKPASS or SUDO_ASKPASS
+    Askpass {
+        /// Prompt text
+        prompt: Vec<String>,
+    },
+    /// Act as a pinentry program for gpg-agent
+    #[structopt(setting = AppSettings::TrailingVarArg, setting = AppSettings::AllowLeadingHyphen)]
+    Pinentry {
+        /// Options passed by gpg-agent, only `--display` is used
+        options: Vec<String>,
+    },
+    /// Serve items read-only as a Secret Service provider on the session bus
+    #[structopt(name = "secret-service")]
+    SecretService,
+    /// List items, for scripts
+    List {
+        #[structopt(flatten)]
+        format: Format,
+    },
+    /// List items matching a fuzzy query, best first
+    Search {
+        #[structopt(required = true)]
+        query: Vec<String>,
+        #[structopt(flatten)]
+        format: Format,
+    },
+    /// Print an item's fields, exiting with 2 when not found and 3 when the name is ambiguous
+    Get {
+        /// Item name or UUID
+        query: String,
+        /// Only print this field, by designation or name
+        #[structopt(long)]
+        field: Option<String>,
+        #[structopt(flatten)]
+        format: Format,
+    },
+    /// Forget every session token and cached value, for screen locker and idle hooks
+    Lock,
+    /// Lock, and also sign out of op
+    Logout {
+        /// Account shorthand, every account if not given
+        account: Option<String>,
+    },
+    /// Lock whenever the machine goes to sleep, the session locks or the screensaver starts
+    Watch,
+    /// Make up a password or diceware passphrase and copy it
+    Generate {
+        /// aws, pin6, legacy-16 or one from the configuration
+        #[structopt(long)]
+        preset: Option<String>,
+        /// Number of characters
+        #[structopt(long)]
+        length: Option<usize>,
+        /// Number of diceware words, for a passphrase instead
+        #[structopt(long, conflicts_with = "length")]
+        words: Option<usize>,
+        /// Between diceware words, `-` by default
+        #[structopt(long)]
+        separator: Option<String>,
+        #[structopt(long)]
+        no_lowercase: bool,
+        #[structopt(long)]
+        no_uppercase: bool,
+        #[structopt(long)]
+        no_digits: bool,
+        #[structopt(long)]
+        no_symbols: bool,
+        /// Only use these symbols
+        #[structopt(long, conflicts_with = "no-symbols")]
+        symbols: Option<String>,
+        /// Leave out characters that are easily mistaken for each other, such as 0 and O
+        #[structopt(long)]
+        exclude_ambiguous: bool,
+        /// Print it instead of copying it
+        #[structopt(long)]
+        print: bool,
+    },
+    /// Answer a browser extension through native messaging
+    #[structopt(name = "native-host", setting = AppSettings::TrailingVarArg, setting = AppSettings::AllowLeadingHyphen)]
+    NativeHost {
+        /// Manifest path and extension passed by the browser, which are ignored
+        #[structopt(hidden = true)]
+        _browser_args: Vec<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct Format {
+    /// Print a single JSON document
+    #[structopt(long, conflicts_with = "ndjson")]
+    json: bool,
+    /// Print a JSON document per line
+    #[structopt(long)]
+    ndjson: bool,
+}
+
+/// Launcher prompt while op can't reach 1Password
+const OFFLINE_PROMPT: &str = "Offline, secrets unavailable:";
+
+/// Exit codes of `get`, so scripts can tell failures apart
+const EXIT_NOT_FOUND: i32 = 2;
+const EXIT_AMBIGUOUS: i32 = 3;
+
+/// Program names that select a subcommand when r1pw is run through a symlink
+const SYMLINKS: &[(&str, &str)] = &[
+    ("git-credential-r1pw", "git-credential"),
+    ("docker-credential-r1pw", "docker-credential"),
+    ("r1pw-askpass", "askpass"),
+    ("r1pw-pinentry", "pinentry"),
+    ("pinentry-r1pw", "pinentry"),
+    ("r1pw-native-host", "native-host"),
+];
+
+fn args() -> Vec<OsString> {
+    let mut args = env::args_os().collect::<Vec<_>>();
+    let program = args.first()
+        .and_then(|a| Path::new(a).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Some((_, subcommand)) = SYMLINKS.iter().find(|(name, _)| program == *name) {
+        args.insert(1, subcommand.into());
+    }
+    args
+}
 
 fn format_login_prompt(account: &Account) -> String {
     format!("Unlock for {} ({}):", account.shorthand, account.email)
 }
 
 fn query_or_login<T, F: Fn(&str) -> Result<T>>(shorthand: &str, prompt: &str, token: &mut Token, query_function: F) -> Result<T> {
+    let query_function = |t: &str| cache::note_offline(query_function(t));
     match &token {
         Token::Fresh(t) => query_function(t),
+        Token::Locked => match attempt_login(shorthand, prompt)? {
+            None => Err(Error::msg("Login cancelled. Unable to proceed without token")),
+            Some(t) => {
+                let result = query_function(&t);
+                *token = Token::Fresh(t);
+                result
+            }
+        },
         Token::Stale(t) => {
             match query_function(t) {
                 Ok(result) => {
                     *token = Token::Fresh(t.into());
                     Ok(result)
                 },
+                // Signing in again won't help an op that doesn't answer, or can't reach 1Password
+                Err(e) if e.is::<op::Timeout>() || e.is::<op::Offline>() => Err(e),
                 Err(e) => {
                     warn!("Token is stale, requesting new one: {}", e);
                     match attempt_login(shorthand, prompt)? {
@@ -46,16 +292,23 @@ fn query_or_login<T, F: Fn(&str) -> Result<T>>(shorthand: &str, prompt: &str, to
 fn obtain_token(account: &storage::Account) -> Result<Option<Token>> {
     Ok(match &account.token {
         Some(t) => Some(Token::Stale(t.into())),
-        None => match attempt_login(&account.shorthand, &format!("Unlock for {} ({}):", account.shorthand, account.email))? {
-            None => None,
-            Some(t) => Some(Token::Fresh(t)),
-        }
+        None => attempt_login(&account.shorthand, &format!("Unlock for {} ({}):", account.shorthand, account.email))?
+            .map(Token::Fresh),
     })
 }
 
+// A lock elsewhere applies to tokens held since startup too
+fn forget_tokens(accounts: &mut [logical::Account]) {
+    debug!("Locked, forgetting tokens");
+    session::tokens_read();
+    for a in accounts {
+        a.token = Token::Locked;
+    }
+}
+
 fn attempt_login(shorthand: &str, prompt: &str) -> Result<Option<String>> {
-    dmenu::prompt_hidden(prompt)?
-        .map(|pw| op::login(shorthand, &pw))
+    launcher::current().prompt_hidden(prompt)?
+        .map(|pw| cache::note_offline(op::login(shorthand, &pw)))
         .transpose()
 }
 
@@ -63,15 +316,411 @@ fn noop() -> Result<()> {
     Ok(())
 }
 
-fn copy_to_clipboard(field: &logical::FullField) {
+fn copy_to_clipboard(field: &logical::FullField) -> Result<()> {
     debug!("Chosen field is: {}, {}, {}", field.name, field.designation, field.value);
-    clipboard::copy_to_clipboard(&field.value);
+    launcher::current().copy(&field.value)
+}
+
+/// What to do with the chosen item
+enum Output {
+    Clipboard,
+    /// Auto-type the chosen field
+    Type,
+    /// Auto-type a sequence of the item's fields and keys
+    Sequence(String),
+}
+
+fn output_field(field: &logical::FullField, output: &Output, config: &Config) -> Result<()> {
+    match output {
+        Output::Clipboard => copy_to_clipboard(field)?,
+        Output::Type | Output::Sequence(_) => autotype::type_value(&field.value, &config.autotype)?,
+    }
+    Ok(())
+}
+
+fn get_full_fields(item_uuid: &str, token: &str) -> Result<Vec<logical::FullField>> {
+    let fields = op::get_credentials(item_uuid, token)?
+        .details.get_fields().into_iter().map(|f| logical::FullField {
+            name: f.name,
+            designation: f.designation,
+            value: f.value,
+            section: f.section,
+        })
+        .collect::<Vec<_>>();
+    Ok(fields)
+}
+
+// Values cached for the item if it is kept locally, from op otherwise
+fn fetch_fields(a: &mut logical::Account, item: &logical::Item) -> Result<Vec<logical::FullField>> {
+    if let Some(fields) = value_cache::get(item) {
+        return Ok(fields)
+    }
+    let fields = query_or_login(&a.shorthand, &format_login_prompt(a), &mut a.token, |t| get_full_fields(&item.uuid, t))?;
+    value_cache::put(item, &fields);
+    Ok(fields)
 }
 
 // Main flow
 fn main() -> Result<()>{
     pretty_env_logger::init();
+    let opt = Opt::from_iter(args());
+    let config = config::read()?;
+    launcher::init(opt.launcher.unwrap_or(config.launcher));
+    commands::init(config.commands.clone());
+    value_cache::init(config.value_cache.clone());
+    if let Some(timeout) = opt.timeout {
+        op::set_timeout(Duration::from_secs(timeout));
+    }
+    // Nothing is ever written back to 1Password, so helpers can only read
+    match &opt.command {
+        Some(Command::GitCredential { action }) if action != "get" => {
+            git_credential::read(io::stdin().lock())?;
+            return Ok(())
+        },
+        Some(Command::DockerCredential { action }) if action == "store" || action == "erase" => {
+            io::stdin().read_to_string(&mut String::new())?;
+            println!("r1pw is read-only, change registry credentials in 1Password instead");
+            std::process::exit(1);
+        },
+        // Locking must work without unlocking first
+        Some(Command::Lock) => return session::lock(),
+        Some(Command::Logout { account }) => return session::logout(account.clone()),
+        Some(Command::Watch) => return watch::watch(),
+        Some(Command::Generate { preset, length, words, separator, no_lowercase, no_uppercase, no_digits, no_symbols, symbols, exclude_ambiguous, print }) => {
+            let given = Recipe {
+                length: *length,
+                words: *words,
+                separator: separator.clone(),
+                lowercase: Some(false).filter(|_| *no_lowercase),
+                uppercase: Some(false).filter(|_| *no_uppercase),
+                digits: Some(false).filter(|_| *no_digits),
+                symbols: Some(false).filter(|_| *no_symbols),
+                symbol_set: symbols.clone(),
+                exclude_ambiguous: Some(true).filter(|_| *exclude_ambiguous),
+            };
+            let generated = generate::generate(&generate::recipe(&config.generate, preset.as_deref(), given)?)?;
+            if *print {
+                println!("{}", generated.value);
+            } else {
+                launcher::current().copy(&generated.value)?;
+                eprintln!("Copied {} to the clipboard", generated.description);
+            }
+            eprintln!("About {:.0} bits of entropy", generated.entropy.floor());
+            return Ok(())
+        },
+        // gpg-agent gives up on a pinentry that doesn't greet it soon, so unlocking waits for GETPIN
+        Some(Command::Pinentry { options }) => {
+            // gpg-agent may have been started outside of the graphical session
+            let display = options.iter().skip_while(|o| *o != "--display").nth(1);
+            if let (Some(display), None) = (display, env::var_os("DISPLAY")) {
+                env::set_var("DISPLAY", display);
+            }
+            let filter = Filter::new(opt.tags.clone(), opt.accounts.clone(), opt.vaults.clone());
+            let mut loaded = None;
+            let stdin = io::stdin();
+            let getpin = |state: &pinentry::State| -> Result<Option<String>> {
+                if loaded.is_none() {
+                    loaded = Some(load()?);
+                }
+                let (accounts, items) = loaded.as_mut().unwrap();
+                let candidates = require_items(visible_items(items, &filter, &config), &filter)?;
+                // A wrong answer from a rule would only be repeated, so let the user choose after one
+                let rule = config.pinentry.rules.iter().find(|rule| state.error.is_none() && pinentry::matches(rule, state));
+                if let Some(rule) = rule {
+                    let reference = reference::parse(&rule.secret)?;
+                    return Ok(Some(resolve_references(accounts, items, &candidates, vec![("pinentry", reference)])?.remove(0).1))
+                }
+                let prompt = state.summary();
+                if let Some(secret) = ask_for_password(accounts, items, candidates, &prompt)? {
+                    return Ok(Some(secret))
+                }
+                // Passphrases not kept in 1Password can still be typed in
+                Ok(launcher::current().prompt_hidden(&prompt)?)
+            };
+            let confirm = |state: &pinentry::State| -> Result<bool> {
+                let options = ["OK", "Cancel"];
+                Ok(matches!(
+                    select_or_query(&options, Some(&state.summary()), |o| o.to_string(), noop)?,
+                    Some(Selection::Item(&"OK"))
+                ))
+            };
+            return pinentry::serve(stdin.lock(), io::stdout(), getpin, confirm)
+        },
+        // Confirmations (host keys, security key touches) are never answered for the user
+        Some(Command::Askpass { .. }) => match env::var("SSH_ASKPASS_PROMPT").as_deref() {
+            Ok("confirm") => return Err(Error::msg("r1pw does not answer ssh confirmations")),
+            Ok("none") => return Ok(()),
+            _ => {},
+        },
+        _ => {},
+    }
+    let filter = Filter::new(opt.tags, opt.accounts, opt.vaults);
+    let (mut accounts, items) = load()?;
+    let candidates = visible_items(&items, &filter, &config);
+    // Scripting commands answer with an empty result, or their exit code for not found
+    let candidates = match opt.command {
+        Some(Command::List { .. }) | Some(Command::Search { .. }) | Some(Command::Get { .. }) => candidates,
+        _ => require_items(candidates, &filter)?,
+    };
+
+    let mut shortcut = None;
+    let offline_prompt = if cache::is_offline() { Some(OFFLINE_PROMPT) } else { None };
+    let selection = match opt.command {
+        None => pick_item_or_shortcut(fuzzy::by_frecency(&candidates, &history::read()?), offline_prompt)?.map(|(item, s)| {
+            shortcut = s;
+            item
+        }),
+        Some(Command::Match { url, window }) => {
+            let ranked = match (url, window) {
+                (Some(url), false) => {
+                    let hosts = url_match::host(&url)
+                        .map(|host| vec![host])
+                        .ok_or_else(||Error::msg(format!("No host found in {}", url)))?;
+                    url_match::rank(candidates, |item| url_match::url_score(&hosts, item))
+                },
+                (None, true) => {
+                    let title = window::active_title()?;
+                    debug!("Active window title: {}", title);
+                    let hosts = url_match::hosts_in_text(&title);
+                    url_match::rank(candidates, |item|
+                        url_match::url_score(&hosts, item).max(url_match::title_score(&title, item))
+                    )
+                },
+                _ => return Err(Error::msg("Either a URL or --window must be given to match")),
+            };
+            debug!("{} of {} items matched", ranked.matched, ranked.items.len());
+            // A single URL match needs no confirmation, the name showing up in a title is only a hint
+            if ranked.matched == 1 && ranked.best >= url_match::Score::Domain {
+                Some(ranked.items[0])
+            } else {
+                pick_item(ranked.items)?
+            }
+        },
+        Some(Command::GitCredential { .. }) => return git_credential_get(&mut accounts, &items, candidates),
+        Some(Command::DockerCredential { action }) if action == "list" => return docker_credential_list(&accounts, &items, candidates),
+        Some(Command::DockerCredential { .. }) => return docker_credential_get(&mut accounts, &items, candidates),
+        // Handled before loading
+        Some(Command::Lock) | Some(Command::Logout { .. }) | Some(Command::Watch) | Some(Command::Generate { .. })
+        | Some(Command::Pinentry { .. }) => unreachable!(),
+        Some(Command::Run { env, env_files, mask, args }) => {
+            let mut variables = vec![];
+            for path in env_files {
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(||format!("Error reading env file {:?}", path))?;
+                variables.extend(reference::parse_env_file(&contents)
+                    .with_context(||format!("Error in env file {:?}", path))?);
+            }
+            for assignment in env {
+                variables.push(reference::parse_assignment(&assignment)?);
+            }
+            let env = resolve_references(&mut accounts, &items, &candidates, variables)?;
+            if mask {
+                std::process::exit(run::run_masked(&args, &env)?);
+            }
+            return run::exec(&args, &env)
+        },
+        Some(Command::Askpass { prompt }) => {
+            let prompt = askpass::parse(&prompt.join(" "));
+            debug!("Askpass prompt: {:?}", prompt);
+            let secret = match config.askpass.rules.iter().find(|rule| askpass::matches(rule, &prompt)) {
+                Some(rule) => {
+                    let reference = reference::parse(&rule.secret)?;
+                    resolve_references(&mut accounts, &items, &candidates, vec![(&prompt.text, reference)])?
+                        .remove(0).1
+                },
+                None => match ask_for_password(&mut accounts, &items, candidates, &prompt.text)? {
+                    Some(secret) => secret,
+                    None => return Err(Error::msg("User cancelled askpass")),
+                },
+            };
+            println!("{}", secret);
+            return Ok(())
+        },
+        Some(Command::SecretService) => {
+            let entries = candidates.into_iter().map(secret_service::Entry::from).collect();
+            let mut lock_check = session::LockCheck::new();
+            let mut items = items;
+            let fetch = move |uuid: &str| -> Result<String> {
+                if lock_check.locked() {
+                    forget_tokens(&mut accounts);
+                }
+                let index = items.iter().position(|i| i.uuid == uuid)
+                    .ok_or_else(||Error::msg(format!("No item {}", uuid)))?;
+                let item = &items[index];
+                let a = accounts.get_mut(item.account_index).unwrap();
+                let fields = fetch_fields(a, item)?;
+                // Other items than logins have their first concealed field designated instead
+                let secret = find_named_field(&fields, "password")
+                    .map(|f| f.value.clone())
+                    .ok_or_else(||Error::msg(format!("Item {} has no password", item.name)))?;
+                // Served for as long as the session lasts, every write must keep what earlier reads found
+                remember_fields(&mut items[index], &fields);
+                convert_cache(&accounts, &items, &[])?;
+                Ok(secret)
+            };
+            return secret_service::serve(entries, Box::new(fetch))
+        },
+        Some(Command::List { format }) => {
+            convert_cache(&accounts, &items, &[])?;
+            let list = candidates.iter().map(|i| output::Item::from(*i)).collect::<Vec<_>>();
+            if format.json {
+                println!("{}", serde_json::to_string(&list)?);
+            } else if format.ndjson {
+                for item in &list {
+                    println!("{}", serde_json::to_string(item)?);
+                }
+            } else {
+                for item in &candidates {
+                    println!("{}", format_item(item));
+                }
+            }
+            return Ok(())
+        },
+        Some(Command::Search { query, format }) => {
+            convert_cache(&accounts, &items, &[])?;
+            let ranked = fuzzy::rank(&candidates, &query.join(" "), &history::read()?);
+            let list = ranked.iter().map(|(item, _)| output::Item::from(*item)).collect::<Vec<_>>();
+            if format.json {
+                println!("{}", serde_json::to_string(&list)?);
+            } else if format.ndjson {
+                for item in &list {
+                    println!("{}", serde_json::to_string(item)?);
+                }
+            } else {
+                for (item, score) in &ranked {
+                    debug!("{} scored {}", item.name, score);
+                    println!("{}", format_item(item));
+                }
+            }
+            return Ok(())
+        },
+        Some(Command::Get { query, field, format }) => {
+            let selection = match reference::find(&candidates, None, &query) {
+                Ok(item) => item,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(match e {
+                        reference::LookupError::NotFound(_) => EXIT_NOT_FOUND,
+                        reference::LookupError::Ambiguous(..) => EXIT_AMBIGUOUS,
+                    });
+                },
+            };
+            let a = accounts.get_mut(selection.account_index).unwrap();
+            let fields = fetch_fields(a, selection)?;
+            convert_cache(&accounts, &items, &[(selection, &fields)])?;
+            match field {
+                Some(name) => {
+                    let field = match find_named_field(&fields, &name) {
+                        Some(field) => field,
+                        None => {
+                            eprintln!("Error: Item {} has no field {}", selection.name, name);
+                            std::process::exit(EXIT_NOT_FOUND);
+                        },
+                    };
+                    if format.json || format.ndjson {
+                        println!("{}", serde_json::to_string(&output::Field::from(field))?);
+                    } else {
+                        println!("{}", field.value);
+                    }
+                },
+                None if format.json => println!("{}", serde_json::to_string(&output::ItemWithFields {
+                    item: output::Item::from(selection),
+                    fields: fields.iter().map(output::Field::from).collect(),
+                })?),
+                None if format.ndjson => for field in &fields {
+                    println!("{}", serde_json::to_string(&output::Field::from(field))?);
+                },
+                None => for field in &fields {
+                    println!("{}", format_field(field));
+                },
+            }
+            return Ok(())
+        },
+        Some(Command::NativeHost { .. }) => {
+            let stdin = io::stdin();
+            let mut input = stdin.lock();
+            let mut output = io::stdout();
+            let mut lock_check = session::LockCheck::new();
+            while let Some(request) = native_host::read(&mut input)? {
+                if lock_check.locked() {
+                    forget_tokens(&mut accounts);
+                }
+                let response = match request {
+                    Ok(request) => native_host_reply(&mut accounts, &items, &candidates, request),
+                    Err(e) => native_host::Response {
+                        id: None,
+                        reply: native_host::Reply::Error { message: format!("Bad request: {}", e) },
+                    },
+                };
+                native_host::write(&mut output, &response)?;
+            }
+            return Ok(())
+        },
+        Some(Command::Inject { input, output }) => {
+            let contents = std::fs::read_to_string(&input)
+                .with_context(||format!("Error reading template {:?}", input))?;
+            let placeholders = template::placeholders(&contents);
+            let mut references = vec![];
+            let mut errors = vec![];
+            for placeholder in &placeholders {
+                match reference::parse(&placeholder.text) {
+                    Ok(r) => references.push((placeholder.location, r)),
+                    Err(e) => errors.push(format!("{}: {}", placeholder.location, e)),
+                }
+            }
+            report_errors(errors)?;
+            let values = resolve_references(&mut accounts, &items, &candidates, references)?
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>();
+            let rendered = template::render(&contents, &placeholders, &values);
+            return match output {
+                Some(output) => cache::write_private(&output, &rendered),
+                None => {
+                    print!("{}", rendered);
+                    Ok(())
+                },
+            }
+        },
+    };
+
+    // @TODO: show previous selected item, if set.
+    // @TODO: save previous item selection
+    if let Some(selection) = selection {
+        history::record(&selection.uuid)?;
+    }
+    if let (Some(selection), Some(shortcut)) = (selection, shortcut) {
+        return match copy_shortcut(&mut accounts, &items, selection, shortcut) {
+            Err(e) if e.is::<op::Offline>() => use_cached_metadata(selection, &Output::Clipboard, &config, e),
+            result => result,
+        }
+    }
+    if let Some(selection) = selection {
+        let autotype = opt.autotype;
+        let sequence = opt.sequence.or_else(|| if autotype {
+            config.autotype.items.get(&selection.name)
+                .or_else(|| config.autotype.items.get(&selection.uuid))
+                .cloned()
+        } else {
+            None
+        });
+        let output = match sequence {
+            Some(sequence) => Output::Sequence(sequence),
+            None if autotype => Output::Type,
+            None => Output::Clipboard,
+        };
+        match use_item(&mut accounts, &items, selection, &output, &config) {
+            Err(e) if e.is::<op::Offline>() => use_cached_metadata(selection, &output, &config, e)?,
+            result => result?,
+        }
+    }
+    // Everything is Ok
+    Ok(())
+}
+
+fn load() -> Result<(Vec<logical::Account>, Vec<logical::Item>)> {
     // Read config and cache (as storage::Cache) and convert it into Logical cache
+    session::tokens_read();
     let cache = cache::read()?;
     if cache.accounts.is_empty() {
         return Err(Error::msg("No accounts found"))
@@ -86,7 +735,8 @@ fn main() -> Result<()>{
                     token,
                     shorthand: account.shorthand.clone(),
                     email: account.email.clone(),
-                    uuid: account.uuid.clone()
+                    uuid: account.uuid.clone(),
+                    items_version: account.items_version,
                 }
             ).ok_or(Error::msg("Must have a token")));
             match nested_result {
@@ -97,6 +747,7 @@ fn main() -> Result<()>{
         })
         .collect::<Result<Vec<logical::Account>>>()?;
 
+    let accounts_cached = cache.accounts.iter().any(|a| !a.items.is_empty());
     // Compute items from all the accounts, querying for real items where they are empty
     let items = cache.accounts
         .into_iter()
@@ -110,13 +761,18 @@ fn main() -> Result<()>{
                     name: field.name.clone(),
                     designation: field.designation.clone(),
                     value_length: field.value_length,
+                    section: field.section.clone(),
                 }).collect::<Vec<_>>();
 
                 logical::Item {
-                    account_name: (&a.shorthand).clone(),
+                    account_name: a.shorthand.clone(),
                     account_index: index,
                     uuid: item.uuid.clone(),
                     name: item.name.clone(),
+                    username: item.username.clone(),
+                    vault_uuid: item.vault_uuid.clone(),
+                    vault_name: item.vault_name.clone(),
+                    category: item.category.clone(),
                     url: item.url.to_owned(),
                     tags: item.tags.clone(),
                     fields: if ! item_fields.is_empty() {
@@ -126,96 +782,457 @@ fn main() -> Result<()>{
                     },
                 }
             }).collect::<Vec<_>>();
-            if items.is_empty() {
+            // Items cached by an older version lack details, such as their vault, and are listed again
+            if items.is_empty() || a.items_version < cache::ITEMS_VERSION {
                 // @TODO: Deal with this unwrap somehow
                 let account_shorthand = &a.shorthand;
-                let op_items = query_or_login(account_shorthand, &format_login_prompt(a), &mut a.token, |t|op::get_items(account_shorthand, t))?;
-                let items = op_items.into_iter().map(|item| logical::Item {
-                    account_name: account_shorthand.clone(),
-                    account_index: index,
-                    uuid: item.uuid,
-                    name: item.overview.title,
-                    url: item.overview.url,
-                    tags: item.overview.tags.into_iter().flatten().collect_vec(),
-                    fields: logical::Fields::Missing(),
+                let list = |t: &str| Ok((op::get_items(account_shorthand, t)?, op::get_vaults(account_shorthand, t)?));
+                let (op_items, vaults) = match query_or_login(account_shorthand, &format_login_prompt(a), &mut a.token, list) {
+                    Ok(listed) => listed,
+                    // Outdated items are better than none, as are other accounts' cached items
+                    Err(e) if e.is::<op::Offline>() && accounts_cached => {
+                        warn!("Skipping account {}: {:#}", account_shorthand, e);
+                        return Ok(items)
+                    },
+                    Err(e) => return Err(e),
+                };
+                a.items_version = cache::ITEMS_VERSION;
+                let mut cached = items;
+                let items = op_items.into_iter().map(|item| {
+                    // Field lengths cached before are still good
+                    let fields = cached.iter().position(|i| i.uuid == item.uuid)
+                        .map(|index| cached.swap_remove(index).fields)
+                        .unwrap_or(logical::Fields::Missing());
+                    logical::Item {
+                        category: item.category().map(|c| c.to_owned()),
+                        username: item.username(),
+                        account_name: account_shorthand.clone(),
+                        account_index: index,
+                        uuid: item.uuid,
+                        name: item.overview.title,
+                        vault_name: item.vault_uuid.as_ref()
+                            .and_then(|uuid| vaults.iter().find(|v| v.uuid == *uuid))
+                            .map(|v| v.name.clone()),
+                        vault_uuid: item.vault_uuid,
+                        url: item.overview.url,
+                        tags: item.overview.tags.into_iter().flatten().collect_vec(),
+                        fields,
+                    }
                 }).collect::<Vec<_>>();
                 return Ok(items);
             }
-            return Ok(items);
+            Ok(items)
         })
         .collect::<Result<Vec<_>>>();
 
-    let items: Vec<logical::Item> = items?.into_iter().flat_map(|a|a)
+    let items: Vec<logical::Item> = items?.into_iter().flatten()
         .collect::<Vec<_>>();
+    Ok((accounts, items))
+}
 
-    // @TODO: show previous selected item, if set.
-    // @TODO: save previous item selection
-    if let Some(selection) = select(&items, |item| {
-        return format!("{} ({})", item.name, item.account_name)
-    }, noop)? {
-        // Display cached list if not empty
-        let a = accounts.get_mut(selection.account_index).unwrap();
-        match &selection.fields {
-            // Simply fetch them from remote and display
-            Fields::Missing() => {
-                let fields = query_or_login(&a.shorthand, &format_login_prompt(a), &mut a.token, |t|op::get_credentials(&selection.uuid, t))?;
-                // Convert to actual fields
-                let fields: Vec<logical::FullField> = fields.details.get_fields().into_iter().map(|f| logical::FullField {
-                    name: f.name,
-                    designation: f.designation,
-                    value: f.value
-                })
-                    .collect::<Vec<_>>();
-                let field = select(&fields, |field| format_field(field), noop)?
-                    .ok_or(Error::msg("User cancelled field choice"))?;
-                copy_to_clipboard(field);
-                convert_cache(&accounts, &items, selection, &fields)?;
-            },
-            Fields::Redacted(fields) => {
-                // At the same time attempt to fetch selected item's real values
-                let mut full_fields: Option<Vec<logical::FullField>> = None;
-                let query_full_fields = || -> Result<()>{
-                    debug!("Running something in the closure");
-                    op::get_credentials(&selection.uuid, &String::from(&a.token)).map(|credential| {
-                        credential.details.get_fields().into_iter().map(|f| logical::FullField {
-                            name: f.name,
-                            designation: f.designation,
-                            value: f.value,
-                        }).collect::<Vec<_>>()
-                    }).map(|fields| full_fields.replace(fields));
-                    Ok(())
-                };
-                let selected_field = select(&fields, |field| format_redacted_field(field), query_full_fields)?
-                    .ok_or(Error::msg("User cancelled field choice"))?;
-
-                let full_fields = full_fields.unwrap_or_else(||{
-                    query_or_login(
-                        &a.shorthand, &format_login_prompt(a), &mut a.token, |t| {
-                            let fields = op::get_credentials(&selection.uuid, t)?
-                                .details.get_fields().into_iter().map(|f| logical::FullField {
-                                name: f.name,
-                                designation: f.designation,
-                                value: f.value,
-                            })
-                                .collect::<Vec<_>>();
-                            Ok(fields)
-                        }).unwrap()
+// Items the filter and the configured vaults let through. Naming vaults on the command line
+// reaches the ones configuration hides.
+fn visible_items<'a>(items: &'a [logical::Item], filter: &Filter, config: &Config) -> Vec<&'a logical::Item> {
+    items.iter()
+        .filter(|i| filter.matches(i) && (filter.names_vaults() || filter::vault_allowed(&config.vaults, i)))
+        .collect()
+}
+
+// Fail when the filter left nothing to choose from
+fn require_items<'a>(candidates: Vec<&'a logical::Item>, filter: &Filter) -> Result<Vec<&'a logical::Item>> {
+    if candidates.is_empty() && filter.is_empty() {
+        return Err(Error::msg("No items to choose from"))
+    }
+    if candidates.is_empty() {
+        return Err(Error::msg(format!("No items match filter {}", filter)))
+    }
+    Ok(candidates)
+}
+
+// Prefer the items that pass, keeping all of them when none do
+fn narrow<F: Fn(&logical::Item) -> bool>(items: Vec<&logical::Item>, keep: F) -> Vec<&logical::Item> {
+    let narrowed = items.iter().copied().filter(|i| keep(i)).collect::<Vec<_>>();
+    if narrowed.is_empty() {
+        items
+    } else {
+        narrowed
+    }
+}
+
+// Items for exactly the URL's host, over the same scheme, so no other site gets their credentials
+fn same_site<'a>(candidates: Vec<&'a logical::Item>, url: &str) -> Vec<&'a logical::Item> {
+    let (hosts, scheme) = match (url_match::host(url), url_match::scheme(url)) {
+        (Some(host), Some(scheme)) => (vec![host], scheme),
+        _ => return vec![],
+    };
+    candidates.into_iter()
+        .filter(|i| url_match::url_score(&hosts, i) == url_match::Score::Host)
+        .filter(|i| i.url.as_deref().and_then(url_match::scheme).as_ref() == Some(&scheme))
+        .collect()
+}
+
+// Answer git with the credentials of the item whose URL matches, staying silent when none do.
+// Only the exact host and scheme count, a credential for `gist.github.com` must never go to
+// `evil.github.com`, and a username or path git asks for must match too.
+fn git_credential_get(accounts: &mut [logical::Account], items: &[logical::Item], candidates: Vec<&logical::Item>) -> Result<()> {
+    let request = git_credential::read(io::stdin().lock())?;
+    debug!("Git credential request: {:?}", request);
+    let url = match request.url() {
+        Some(url) => url,
+        None => return Ok(()),
+    };
+    let mut matches = same_site(candidates, &url).into_iter()
+        .filter(|i| request.username.is_none() || i.username == request.username)
+        .collect::<Vec<_>>();
+    if let Some(path) = &request.path {
+        // The item naming the longest part of the path is the most specific one
+        let depth = |i: &logical::Item| i.url.as_deref().and_then(|url| url_match::path_depth(url, path));
+        matches.retain(|i| depth(i).is_some());
+        let deepest = matches.iter().map(|i| depth(i)).max().flatten();
+        matches.retain(|i| depth(i) == deepest);
+    }
+    let selection = match matches.len() {
+        0 => return Ok(()),
+        1 => matches[0],
+        _ => match pick_item(matches)? {
+            Some(item) => item,
+            None => return Ok(()),
+        },
+    };
+
+    let a = accounts.get_mut(selection.account_index).unwrap();
+    let fields = fetch_fields(a, selection)?;
+    let password = find_field(&fields, "password")
+        .ok_or_else(||Error::msg(format!("Item {} has no password", selection.name)))?;
+    let username = find_field(&fields, "username").map(|f| &f.value)
+        .or(selection.username.as_ref())
+        .or(request.username.as_ref())
+        .ok_or_else(||Error::msg(format!("Item {} has no username", selection.name)))?;
+    print!("{}", git_credential::format(username, &password.value));
+    convert_cache(accounts, items, &[(selection, &fields)])
+}
+
+fn docker_credential_list(accounts: &[logical::Account], items: &[logical::Item], candidates: Vec<&logical::Item>) -> Result<()> {
+    convert_cache(accounts, items, &[])?;
+    let servers = candidates.into_iter()
+        .filter(|i| docker_credential::is_tagged(i))
+        .filter_map(|i| docker_credential::server(i)
+            .map(|server| (server, i.username.clone().unwrap_or_default()))
+        )
+        .collect::<BTreeMap<_, _>>();
+    println!("{}", serde_json::to_string(&servers)?);
+    Ok(())
+}
+
+// Docker expects errors on stdout, and a particular message when nothing matches.
+// Only the registry's exact host counts, credentials for one registry never go to another.
+fn docker_credential_get(accounts: &mut [logical::Account], items: &[logical::Item], candidates: Vec<&logical::Item>) -> Result<()> {
+    let mut server_url = String::new();
+    io::stdin().read_to_string(&mut server_url)?;
+    let server_url = server_url.trim().to_owned();
+    let fail = |message: &str| -> Result<()> {
+        println!("{}", message);
+        std::process::exit(1);
+    };
+    let host = match docker_credential::registry_host(&server_url) {
+        Some(host) => host,
+        None => return fail(docker_credential::NOT_FOUND),
+    };
+    let tagged = candidates.iter().copied()
+        .filter(|i| docker_credential::tagged_host(i).as_ref() == Some(&host))
+        .collect::<Vec<_>>();
+    let matches = if !tagged.is_empty() {
+        tagged
+    } else {
+        let on_host = candidates.into_iter()
+            .filter(|i| i.url.as_deref().and_then(docker_credential::registry_host).as_ref() == Some(&host))
+            .collect();
+        narrow(on_host, |i| i.tags.iter().any(|t| t == docker_credential::TAG))
+    };
+    let selection = match matches.len() {
+        0 => return fail(docker_credential::NOT_FOUND),
+        1 => matches[0],
+        _ => match pick_item(matches)? {
+            Some(item) => item,
+            None => return fail(docker_credential::NOT_FOUND),
+        },
+    };
+
+    let a = accounts.get_mut(selection.account_index).unwrap();
+    let fields = match fetch_fields(a, selection) {
+        Ok(fields) => fields,
+        Err(e) => return fail(&format!("{:#}", e)),
+    };
+    let credentials = docker_credential::Credentials {
+        server_url,
+        username: find_field(&fields, "username").map(|f| f.value.clone())
+            .or_else(|| selection.username.clone())
+            .unwrap_or_default(),
+        secret: match find_field(&fields, "password") {
+            Some(field) => field.value.clone(),
+            None => return fail(&format!("Item {} has no password", selection.name)),
+        },
+    };
+    println!("{}", serde_json::to_string(&credentials)?);
+    convert_cache(accounts, items, &[(selection, &fields)])
+}
+
+// Let the user pick an item to answer a prompt with, using its password unless it has none
+fn ask_for_password(accounts: &mut [logical::Account], items: &[logical::Item], candidates: Vec<&logical::Item>, prompt: &str) -> Result<Option<String>> {
+    let selection = match pick_item_with_prompt(candidates, Some(prompt.trim()))? {
+        Some(item) => item,
+        None => return Ok(None),
+    };
+    let a = accounts.get_mut(selection.account_index).unwrap();
+    let fields = fetch_fields(a, selection)?;
+    let field = match find_field(&fields, "password") {
+        Some(field) => Some(field),
+        None => select(&fields, format_field, noop)?,
+    };
+    let secret = field.map(|f| f.value.clone());
+    convert_cache(accounts, items, &[(selection, &fields)])?;
+    Ok(secret)
+}
+
+// Every login for a page needs to be confirmed in the launcher, even when only one item matches
+fn native_host_reply(accounts: &mut [logical::Account], items: &[logical::Item], candidates: &[&logical::Item], request: native_host::Request) -> native_host::Response {
+    use native_host::{Request, Reply, Response};
+    let id = request.id();
+    let reply = match request {
+        Request::Ping { .. } => Reply::Ok { username: None, password: None },
+        Request::GetCredentials { origin, .. } => {
+            let matches = same_site(candidates.to_vec(), &origin);
+            if matches.is_empty() {
+                Reply::NotFound
+            } else {
+                let prompt = format!("Fill in {}?", origin);
+                let result = pick_item_with_prompt(matches, Some(&prompt)).and_then(|selection| match selection {
+                    None => Ok(Reply::Denied),
+                    Some(selection) => {
+                        let a = accounts.get_mut(selection.account_index).unwrap();
+                        let fields = fetch_fields(a, selection)?;
+                        let reply = Reply::Ok {
+                            username: find_field(&fields, "username").map(|f| f.value.clone())
+                                .or_else(|| selection.username.clone()),
+                            password: find_field(&fields, "password").map(|f| f.value.clone()),
+                        };
+                        convert_cache(accounts, items, &[(selection, &fields)])?;
+                        Ok(reply)
+                    },
                 });
+                result.unwrap_or_else(|e| Reply::Error { message: format!("{:#}", e) })
+            }
+        },
+    };
+    Response { id, reply }
+}
 
-                let field = full_fields.iter()
-                    .find(|i| selected_field.name == i.name)
-                    .ok_or(Error::msg("Selected field not found in full field list"))?;
+// Fail with every error at once, one per line
+fn report_errors(errors: Vec<String>) -> Result<()> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::msg(errors.join("\n")))
+    }
+}
 
-                copy_to_clipboard(field);
-                convert_cache(&accounts, &items, selection, &full_fields)?;
+// Look up each reference's value, fetching every item once. Errors name the key of every bad reference.
+fn resolve_references<K: std::fmt::Display>(accounts: &mut [logical::Account], items: &[logical::Item], candidates: &[&logical::Item], references: Vec<(K, reference::Reference)>) -> Result<Vec<(K, String)>> {
+    let mut errors = vec![];
+    let resolved = references.into_iter()
+        .filter_map(|(key, r)| match reference::resolve(candidates, &r) {
+            Ok(item) => Some((key, r, item)),
+            Err(e) => {
+                errors.push(format!("{}: {}", key, e));
+                None
             },
+        })
+        .collect::<Vec<_>>();
+    report_errors(errors)?;
+
+    let mut fetched: HashMap<&str, (&logical::Item, Vec<logical::FullField>)> = HashMap::new();
+    for (_, _, item) in &resolved {
+        if fetched.contains_key(item.uuid.as_str()) {
+            continue
         }
+        let a = accounts.get_mut(item.account_index).unwrap();
+        let fields = fetch_fields(a, item)?;
+        fetched.insert(&item.uuid, (item, fields));
+    }
+
+    let mut errors = vec![];
+    let values = resolved.into_iter().filter_map(|(key, r, item)| {
+        let (_, fields) = &fetched[item.uuid.as_str()];
+        match find_named_field(fields, &r.field) {
+            Some(f) => Some((key, f.value.clone())),
+            None => {
+                errors.push(format!("{}: Item {} has no field {}", key, item.name, r.field));
+                None
+            },
+        }
+    }).collect::<Vec<_>>();
+    report_errors(errors)?;
+
+    let fetched = fetched.values()
+        .map(|(item, fields)| (*item, fields.as_slice()))
+        .collect::<Vec<_>>();
+    convert_cache(accounts, items, &fetched)?;
+    Ok(values)
+}
+
+fn pick_item(candidates: Vec<&logical::Item>) -> Result<Option<&logical::Item>> {
+    pick_item_with_prompt(candidates, None)
+}
+
+fn pick_item_with_prompt<'a>(candidates: Vec<&'a logical::Item>, prompt: Option<&str>) -> Result<Option<&'a logical::Item>> {
+    Ok(pick_item_or_shortcut(candidates, prompt)?.map(|(item, _)| item))
+}
+
+fn pick_item_or_shortcut<'a>(candidates: Vec<&'a logical::Item>, prompt: Option<&str>) -> Result<Option<(&'a logical::Item, Option<launcher::Shortcut>)>> {
+    // Typing `#tag` or `@account` into the launcher narrows the list further, anything else is searched for
+    let mut filter = Filter::default();
+    let mut candidates = candidates;
+    loop {
+        let visible = candidates.iter().filter(|i| filter.matches(i)).collect::<Vec<_>>();
+        if visible.is_empty() {
+            return Err(Error::msg(format!("No items match filter {}", filter)))
+        }
+        match launch(&visible, prompt, |item| format_item(item), Some(|item: &&&logical::Item| format_preview(item)), noop)? {
+            Some(Selection::Item(item)) => return Ok(Some((**item, None))),
+            Some(Selection::Shortcut(item, shortcut)) => return Ok(Some((**item, Some(shortcut)))),
+            Some(Selection::Query(query)) => match Filter::parse(&query) {
+                Some(query) => filter = filter.and(query),
+                None if query.trim().is_empty() => return Ok(None),
+                None => {
+                    let visible = visible.into_iter().copied().collect::<Vec<_>>();
+                    let ranked = fuzzy::rank(&visible, &query, &history::read()?);
+                    match ranked.len() {
+                        0 => return Err(Error::msg(format!("No items match {}", query))),
+                        1 => return Ok(Some((ranked[0].0, None))),
+                        _ => candidates = ranked.into_iter().map(|(item, _)| item).collect(),
+                    }
+                },
+            },
+            None => return Ok(None),
+        }
+    }
+}
+
+fn use_item(accounts: &mut [logical::Account], items: &[logical::Item], selection: &logical::Item, output: &Output, config: &Config) -> Result<()> {
+    let a = accounts.get_mut(selection.account_index).unwrap();
+    // Sequences use several fields, so there is no field to choose
+    if let Output::Sequence(sequence) = output {
+        let steps = autotype::parse(sequence)?;
+        let fields = fetch_fields(a, selection)?;
+        autotype::type_sequence(&steps, &fields, &config.autotype)?;
+        return convert_cache(accounts, items, &[(selection, &fields)])
+    }
+    // Display cached list if not empty
+    match &selection.fields {
+        // Simply fetch them from remote and display
+        Fields::Missing() => {
+            let fields = fetch_fields(a, selection)?;
+            let field = select(&fields, format_field, noop)?
+                .ok_or(Error::msg("User cancelled field choice"))?;
+            output_field(field, output, config)?;
+            convert_cache(accounts, items, &[(selection, &fields)])?;
+        },
+        Fields::Redacted(fields) => {
+            // At the same time attempt to fetch selected item's real values, unless they're cached
+            let cached = value_cache::get(selection);
+            let mut full_fields: Option<Result<Vec<logical::FullField>>> = None;
+            let query_full_fields = || -> Result<()>{
+                // A locked account is unlocked once a field is chosen
+                if let (None, Some(token)) = (&cached, a.token.value()) {
+                    debug!("Running something in the closure");
+                    full_fields = Some(cache::note_offline(get_full_fields(&selection.uuid, token)));
+                }
+                Ok(())
+            };
+            let selected_field = select(fields, format_redacted_field, query_full_fields)?
+                .ok_or(Error::msg("User cancelled field choice"))?;
+
+            let full_fields = match (cached, full_fields) {
+                (Some(fields), _) => fields,
+                (None, Some(Ok(fields))) => {
+                    value_cache::put(selection, &fields);
+                    fields
+                },
+                // Asking op a second time would only fail the same way
+                (None, Some(Err(e))) if e.is::<op::Timeout>() || e.is::<op::Offline>() => return Err(e),
+                _ => fetch_fields(a, selection)?,
+            };
+
+            let field = full_fields.iter()
+                .find(|i| selected_field.name == i.name && selected_field.section == i.section)
+                .ok_or(Error::msg("Selected field not found in full field list"))?;
+
+            output_field(field, output, config)?;
+            convert_cache(accounts, items, &[(selection, &full_fields)])?;
+        },
     }
-    // Everything is Ok
     Ok(())
 }
 
-fn convert_cache(accounts: &Vec<logical::Account>, items: &Vec<logical::Item>, selected_item: &logical::Item, new_fields: &Vec<logical::FullField>) -> Result<()> {
+// Without 1Password only what the cache knows can be given out
+fn use_cached_metadata(selection: &logical::Item, output: &Output, config: &Config, offline: Error) -> Result<()> {
+    warn!("{:#}", offline);
+    let mut fields = vec![];
+    if let Some(username) = &selection.username {
+        fields.push(logical::FullField { name: "username".to_owned(), designation: "username".to_owned(), value: username.clone(), section: None });
+    }
+    if let Some(url) = &selection.url {
+        fields.push(logical::FullField { name: "url".to_owned(), designation: String::new(), value: url.clone(), section: None });
+    }
+    if fields.is_empty() || matches!(output, Output::Sequence(_)) {
+        return Err(offline.context(format!("Nothing cached for {} can be used offline", selection.name)))
+    }
+    let field = match select_or_query(&fields, Some(OFFLINE_PROMPT), format_field, noop)? {
+        Some(Selection::Item(field)) => field,
+        _ => return Err(Error::msg("User cancelled field choice")),
+    };
+    output_field(field, output, config)
+}
+
+// Copy a field picked with a launcher shortcut, without asking which field
+fn copy_shortcut(accounts: &mut [logical::Account], items: &[logical::Item], selection: &logical::Item, shortcut: launcher::Shortcut) -> Result<()> {
+    let a = accounts.get_mut(selection.account_index).unwrap();
+    let value = match shortcut {
+        launcher::Shortcut::Totp => query_or_login(&a.shorthand, &format_login_prompt(a), &mut a.token, |t| op::get_totp(&selection.uuid, t))?,
+        launcher::Shortcut::Username | launcher::Shortcut::Password => {
+            let name = if shortcut == launcher::Shortcut::Username { "username" } else { "password" };
+            let fields = fetch_fields(a, selection)?;
+            let value = find_field(&fields, name)
+                .map(|f| f.value.clone())
+                .ok_or_else(||Error::msg(format!("Item {} has no {}", selection.name, name)))?;
+            convert_cache(accounts, items, &[(selection, &fields)])?;
+            value
+        },
+    };
+    launcher::current().copy(&value)
+}
+
+// Keep what fetching an item's fields showed in the item itself, the values only by their length
+fn remember_fields(item: &mut logical::Item, fields: &[logical::FullField]) {
+    item.fields = Fields::Redacted(fields.iter().map(|f| logical::RedactedField {
+        name: f.name.clone(),
+        designation: f.designation.clone(),
+        value_length: f.value.len(),
+        section: f.section.clone(),
+    }).collect());
+    if item.username.is_none() {
+        item.username = fields.iter()
+            .find(|f| f.designation == "username")
+            .map(|f| f.value.clone());
+    }
+}
+
+fn convert_cache(accounts: &[logical::Account], items: &[logical::Item], fetched: &[(&logical::Item, &[logical::FullField])]) -> Result<()> {
+    // `r1pw lock` ran while these tokens were in use, they must not be written back
+    let locked = session::tokens_locked();
+    if locked {
+        info!("Locked meanwhile, leaving tokens out of the cache");
+    }
     let accounts = accounts.iter().enumerate().map(|(index, a)| {
         let account_items = items.iter()
             .filter(|&i| i.account_index == index)
@@ -225,35 +1242,46 @@ fn convert_cache(accounts: &Vec<logical::Account>, items: &Vec<logical::Item>, s
                         fields.iter().map(|f| storage::Field {
                             name: f.name.clone(),
                             designation: f.designation.clone(),
-                            value_length: f.value_length.clone()
+                            value_length: f.value_length,
+                            section: f.section.clone(),
                         }).collect::<Vec<_>>(),
                     Fields::Missing() => vec![],
                 };
-                // Patch selected item's fields
-                if selected_item.uuid == i.uuid {
+                let mut username = i.username.clone();
+                // Patch fetched items' fields
+                if let Some((_, new_fields)) = fetched.iter().find(|(f, _)| f.uuid == i.uuid) {
                     item_fields = new_fields.iter().map(|f| storage::Field {
                         name: f.name.clone(),
                         designation: f.designation.clone(),
                         value_length: f.value.len(),
+                        section: f.section.clone(),
                     }).collect::<Vec<_>>();
+                    // Items cached before usernames were tracked pick it up from the login field
+                    if username.is_none() {
+                        username = new_fields.iter()
+                            .find(|f| f.designation == "username")
+                            .map(|f| f.value.clone());
+                    }
                 }
                 storage::Item {
                     uuid: i.uuid.clone(),
                     name: i.name.clone(),
+                    username,
+                    vault_uuid: i.vault_uuid.clone(),
+                    vault_name: i.vault_name.clone(),
+                    category: i.category.clone(),
                     url: i.url.clone(),
                     fields: item_fields,
                     tags: i.tags.clone(),
                 }
             }).collect::<Vec<_>>();
         storage::Account {
-            token: Some(match &a.token {
-                Token::Stale(t) => t,
-                Token::Fresh(t) => t,
-            }.clone()),
+            token: a.token.value().filter(|_| !locked).map(|t| t.to_owned()),
             shorthand: a.shorthand.clone(),
             email: a.email.clone(),
             uuid: a.uuid.clone(),
             items: account_items,
+            items_version: a.items_version,
         }
     }).collect();
 
@@ -266,23 +1294,130 @@ fn convert_cache(accounts: &Vec<logical::Account>, items: &Vec<logical::Item>, s
     cache::write(&cache)
 }
 
+fn format_item(item: &logical::Item) -> String {
+    // Items of the same name in different vaults are told apart by them
+    let location = match &item.vault_name {
+        Some(vault) => format!("{}/{}", item.account_name, vault),
+        None => item.account_name.clone(),
+    };
+    let mut line = match &item.username {
+        Some(username) if !username.is_empty() => format!("{} - {} ({})", item.name, username, location),
+        _ => format!("{} ({})", item.name, location),
+    };
+    if let Some(category) = &item.category {
+        line.push_str(&format!(" [{}]", category));
+    }
+    for tag in &item.tags {
+        line.push_str(&format!(" #{}", tag));
+    }
+    line
+}
+
+// Details shown beside the item list, never anything secret
+fn format_preview(item: &logical::Item) -> Vec<String> {
+    let mut lines = vec![item.name.clone(), String::new(), format!("Account: {}", item.account_name)];
+    if let Some(vault) = &item.vault_name {
+        lines.push(format!("Vault: {}", vault));
+    }
+    if let Some(category) = &item.category {
+        lines.push(format!("Category: {}", category));
+    }
+    if let Some(username) = &item.username {
+        lines.push(format!("Username: {}", username));
+    }
+    if let Some(url) = &item.url {
+        lines.push(format!("URL: {}", url));
+    }
+    if !item.tags.is_empty() {
+        lines.push(format!("Tags: {}", item.tags.iter().map(|t| format!("#{}", t)).join(" ")));
+    }
+    lines.push(String::new());
+    match &item.fields {
+        Fields::Redacted(fields) => lines.extend(fields.iter()
+            .map(|f| format!("{}: {}", field_label(&f.name, &f.section), mask(f.value_length)))),
+        Fields::Missing() => lines.push("Fields not cached yet".to_owned()),
+    }
+    lines
+}
+
 fn format_field(field: &logical::FullField) -> String {
-    format!("Designation: {}, Field name: {}, Value: {}", field.designation, field.name, field.value)
+    // Notes span lines, but each field must stay on one
+    let value = field.value.lines().join(" ");
+    format!("Designation: {}, Field name: {}, Value: {}", field.designation, field_label(&field.name, &field.section), value)
 }
 
 fn format_redacted_field(field: &logical::RedactedField) -> String {
-    format!("Designation: {}, Field name: {}, Value: {}", field.designation, field.name, "*".repeat(field.value_length))
+    format!("Designation: {}, Field name: {}, Value: {}", field.designation, field_label(&field.name, &field.section), mask(field.value_length))
+}
+
+// Fields of other item types than logins are grouped in sections
+fn field_label(name: &str, section: &Option<String>) -> String {
+    match section {
+        Some(section) => format!("{} / {}", section, name),
+        None => name.to_owned(),
+    }
+}
+
+// Long notes would push everything else off screen
+fn mask(length: usize) -> String {
+    const MAX_MASK: usize = 32;
+    if length > MAX_MASK {
+        format!("{}… ({} characters)", "*".repeat(MAX_MASK), length)
+    } else {
+        "*".repeat(length)
+    }
+}
+
+enum Selection<'a, T> {
+    Item(&'a T),
+    /// Text typed into the launcher that matches none of the items
+    Query(String),
+    /// Item whose field should be copied straight away
+    Shortcut(&'a T, launcher::Shortcut),
 }
 
-fn select<T, H: Fn(&T) -> String, F: FnOnce() -> Result<()>>(items: &Vec<T>, format: H, foo: F) -> Result<Option<&T>> {
-    let input = items.iter()
-        .map(|item| format(item))
-        .join("\n");
+fn select<T, H: Fn(&T) -> String, F: FnOnce() -> Result<()>>(items: &[T], format: H, while_waiting: F) -> Result<Option<&T>> {
+    Ok(match select_or_query(items, None, format, while_waiting)? {
+        Some(Selection::Item(item)) => Some(item),
+        _ => None,
+    })
+}
+
+fn select_or_query<'a, T, H: Fn(&T) -> String, F: FnOnce() -> Result<()>>(items: &'a [T], prompt: Option<&str>, format: H, while_waiting: F) -> Result<Option<Selection<'a, T>>> {
+    launch(items, prompt, format, None::<fn(&T) -> Vec<String>>, while_waiting)
+}
 
-    let result = dmenu::select(&input, foo)
-        .with_context(||"Error running dmenu")?
-        .map(|choice|
-            items.iter().find(|&i| format(i) == choice)
-        ).flatten();
+// Items with a preview are the ones with fields to copy, so shortcuts are offered with it
+fn launch<'a, T, H, P, F>(items: &'a [T], prompt: Option<&str>, format: H, preview: Option<P>, while_waiting: F) -> Result<Option<Selection<'a, T>>>
+where
+    H: Fn(&T) -> String,
+    P: Fn(&T) -> Vec<String>,
+    F: FnOnce() -> Result<()>,
+{
+    let entries = items.iter()
+        .map(|i| launcher::Entry {
+            line: format(i),
+            preview: preview.as_ref().map(|p| p(i)).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    let menu = launcher::Menu {
+        entries: &entries,
+        prompt,
+        shortcuts: preview.is_some(),
+    };
+    let find = |line: &str| items.iter().zip(&entries).find(|(_, e)| e.line == line).map(|(i, _)| i);
+
+    let result = launcher::current().select(&menu, Box::new(while_waiting))
+        .with_context(||"Error running launcher")?
+        .map(|choice| match choice {
+            launcher::Choice::Line(line) => match find(&line) {
+                Some(item) => Selection::Item(item),
+                None => Selection::Query(line),
+            },
+            launcher::Choice::Shortcut(line, shortcut) => match find(&line) {
+                Some(item) => Selection::Shortcut(item, shortcut),
+                None => Selection::Query(line),
+            },
+        });
     Ok(result)
 }
@@ -1,3 +1,5 @@
 pub mod op;
 pub mod logical;
 pub mod storage;
+pub mod config;
+pub mod output;
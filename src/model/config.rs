@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+// User configuration, every section is optional
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub launcher: LauncherKind,
+    /// External tools keyed by name: op, dmenu, xsel, xdotool and so on
+    pub commands: HashMap<String, Tool>,
+    pub vaults: Vaults,
+    pub value_cache: ValueCache,
+    pub autotype: AutoType,
+    pub askpass: Askpass,
+    pub pinentry: Pinentry,
+    pub generate: Generate,
+}
+
+/// Vaults to list items from, by name or UUID. Without `include` every vault is
+/// listed, `exclude` hides vaults either way. `--vault` overrides both.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Vaults {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Secret values kept encrypted on disk so they can be copied instantly, and offline.
+/// Nothing is cached unless an item or tag is listed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValueCache {
+    /// Item names or UUIDs whose values are cached
+    pub items: Vec<String>,
+    /// Items with any of these tags are cached
+    pub tags: Vec<String>,
+    /// Vaults never cached by name or UUID, whatever else matches
+    pub exclude_vaults: Vec<String>,
+    /// Seconds a cached value can be used for
+    pub ttl: u64,
+}
+
+impl Default for ValueCache {
+    fn default() -> Self {
+        ValueCache {
+            items: vec![],
+            tags: vec![],
+            exclude_vaults: vec![],
+            ttl: 900,
+        }
+    }
+}
+
+/// Where an external tool lives, and arguments to always give it
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tool {
+    /// Path to the executable, looked up on PATH by name when missing
+    pub path: Option<String>,
+    /// Arguments placed before r1pw's own
+    pub args: Vec<String>,
+}
+
+/// Which launcher shows menus and prompts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LauncherKind {
+    /// dmenu when there is a display, the terminal otherwise
+    #[default]
+    Auto,
+    Dmenu,
+    Terminal,
+}
+
+impl FromStr for LauncherKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(LauncherKind::Auto),
+            "dmenu" => Ok(LauncherKind::Dmenu),
+            "terminal" => Ok(LauncherKind::Terminal),
+            _ => Err(format!("Unknown launcher {}, expected auto, dmenu or terminal", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoType {
+    /// Delay before typing starts, giving the launcher time to hand back focus
+    pub start_delay: u64,
+    /// Delay between keystrokes in milliseconds
+    pub key_delay: u64,
+    /// Sequences typed for an item instead of a single field, keyed by item name or UUID
+    pub items: HashMap<String, String>,
+}
+
+impl Default for AutoType {
+    fn default() -> Self {
+        AutoType {
+            start_delay: 250,
+            key_delay: 12,
+            items: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Askpass {
+    /// Checked in order, the first matching rule answers the prompt
+    pub rules: Vec<AskpassRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AskpassRule {
+    /// Path of the private key whose passphrase is asked for
+    #[serde(default)]
+    pub key: Option<String>,
+    /// `host` or `user@host` whose password is asked for
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Text the prompt contains, such as `[sudo]`
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// `<item>/<field>` reference of the answer
+    pub secret: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pinentry {
+    /// Checked in order, the first matching rule answers GETPIN
+    pub rules: Vec<PinentryRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinentryRule {
+    /// Keygrip of the key, as listed by `gpg --with-keygrip -K`
+    #[serde(default)]
+    pub keygrip: Option<String>,
+    /// Text the description contains, such as the key's user ID
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `<item>/<field>` reference of the passphrase
+    pub secret: String,
+}
+
+/// Settings for `r1pw generate`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Generate {
+    /// Recipes by name, replacing built in presets of the same name
+    pub presets: HashMap<String, Recipe>,
+}
+
+/// How to make up a password. Anything left out comes from the defaults.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recipe {
+    /// Number of characters
+    pub length: Option<usize>,
+    /// Number of diceware words, for a passphrase instead
+    pub words: Option<usize>,
+    /// Between diceware words
+    pub separator: Option<String>,
+    pub lowercase: Option<bool>,
+    pub uppercase: Option<bool>,
+    pub digits: Option<bool>,
+    pub symbols: Option<bool>,
+    /// Symbols to pick from, for sites that only accept some
+    pub symbol_set: Option<String>,
+    /// Leave out characters that are easily mistaken for each other, such as 0 and O
+    pub exclude_ambiguous: Option<bool>,
+}
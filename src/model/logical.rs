@@ -1,8 +1,3 @@
-#[derive(Debug)]
-pub struct Cache {
-    pub items: Vec<Item>,
-}
-
 #[derive(Debug)]
 pub enum Fields {
     Redacted(Vec<RedactedField>),
@@ -15,6 +10,17 @@ pub struct FullField {
     pub name: String,
     pub designation: String,
     pub value: String,
+    pub section: Option<String>,
+}
+
+pub fn find_field<'a>(fields: &'a [FullField], designation: &str) -> Option<&'a FullField> {
+    fields.iter().find(|f| f.designation == designation)
+}
+
+/// Field a user named, by designation or failing that, name, ignoring case
+pub fn find_named_field<'a>(fields: &'a [FullField], name: &str) -> Option<&'a FullField> {
+    fields.iter().find(|f| f.designation.eq_ignore_ascii_case(name))
+        .or_else(|| fields.iter().find(|f| f.name.eq_ignore_ascii_case(name)))
 }
 
 #[derive(Debug)]
@@ -22,6 +28,7 @@ pub struct RedactedField {
     pub name: String,
     pub designation: String,
     pub value_length: usize,
+    pub section: Option<String>,
 }
 
 #[derive(Debug)]
@@ -30,22 +37,49 @@ pub struct Item {
     pub account_index: usize,
     pub uuid: String,
     pub name: String,
+    pub username: Option<String>,
+    pub vault_uuid: Option<String>,
+    pub vault_name: Option<String>,
+    pub category: Option<String>,
     pub url: Option<String>,
     pub tags: Vec<String>,
     pub fields: Fields,
 }
 
+#[cfg(test)]
+impl Item {
+    /// Item of the first account with nothing but a name and URL
+    pub fn named(name: &str, url: Option<&str>) -> Item {
+        Item {
+            account_name: "my".to_owned(),
+            account_index: 0,
+            uuid: name.to_lowercase(),
+            name: name.to_owned(),
+            username: None,
+            vault_uuid: None,
+            vault_name: None,
+            category: None,
+            url: url.map(|u| u.to_owned()),
+            tags: vec![],
+            fields: Fields::Missing(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Token {
     Stale(String),
     Fresh(String),
+    /// Dropped by a lock, must unlock again
+    Locked,
 }
 
-impl From<&Token> for String {
-    fn from(t: &Token) -> Self {
-        match t {
-            Token::Stale(t) => t.to_owned(),
-            Token::Fresh(t) => t.to_owned(),
+impl Token {
+    /// Session to pass to op, none while locked
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Token::Stale(t) | Token::Fresh(t) => Some(t),
+            Token::Locked => None,
         }
     }
 }
@@ -56,4 +90,5 @@ pub struct Account {
     pub shorthand: String,
     pub email: String,
     pub uuid: String,
+    pub items_version: u32,
 }
@@ -0,0 +1,59 @@
+use serde::Serialize;
+
+use crate::model::logical;
+
+// Machine readable output. Field names are part of r1pw's interface, keep them stable.
+#[derive(Debug, Serialize)]
+pub struct Item {
+    pub uuid: String,
+    pub name: String,
+    pub account: String,
+    pub vault_uuid: Option<String>,
+    pub vault: Option<String>,
+    pub category: Option<String>,
+    pub username: Option<String>,
+    pub url: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl From<&logical::Item> for Item {
+    fn from(item: &logical::Item) -> Self {
+        Item {
+            uuid: item.uuid.clone(),
+            name: item.name.clone(),
+            account: item.account_name.clone(),
+            vault_uuid: item.vault_uuid.clone(),
+            vault: item.vault_name.clone(),
+            category: item.category.clone(),
+            username: item.username.clone(),
+            url: item.url.clone(),
+            tags: item.tags.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Field {
+    pub name: String,
+    pub designation: String,
+    pub value: String,
+    pub section: Option<String>,
+}
+
+impl From<&logical::FullField> for Field {
+    fn from(field: &logical::FullField) -> Self {
+        Field {
+            name: field.name.clone(),
+            designation: field.designation.clone(),
+            value: field.value.clone(),
+            section: field.section.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemWithFields {
+    #[serde(flatten)]
+    pub item: Item,
+    pub fields: Vec<Field>,
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use serde::{ Serialize, Deserialize};
 
 // Storage model
@@ -12,13 +13,24 @@ pub struct Account {
     pub shorthand: String,
     pub email: String,
     pub uuid: String,
-    pub items: Vec<Item>
+    pub items: Vec<Item>,
+    /// What the items were listed with, see `cache::ITEMS_VERSION`
+    #[serde(default)]
+    pub items_version: u32,
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Item {
     pub uuid: String,
     pub name: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub vault_uuid: Option<String>,
+    #[serde(default)]
+    pub vault_name: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
     pub url: Option<String>,
     pub fields: Vec<Field>,
     pub tags: Vec<String>,
@@ -29,4 +41,35 @@ pub struct Field {
     pub name: String,
     pub designation: String,
     pub value_length: usize,
+    #[serde(default)]
+    pub section: Option<String>,
+}
+
+/// How often and how recently items were chosen, keyed by item UUID
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub items: HashMap<String, Usage>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Usage {
+    pub count: u32,
+    /// Seconds since the epoch
+    pub last_used: u64,
+}
+
+/// Encrypted field values, keyed by item UUID
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SealedValues {
+    pub items: HashMap<String, Sealed>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sealed {
+    /// Seconds since the epoch after which the value is not used
+    pub expires: u64,
+    /// Hex encoded
+    pub nonce: String,
+    /// Hex encoded fields, as JSON
+    pub ciphertext: String,
 }
@@ -0,0 +1,169 @@
+use std::io::{self, Read, Write};
+use anyhow::{Result, Error, Context};
+use serde::{Serialize, Deserialize};
+
+/// Browsers send at most 4GB, but nothing r1pw understands comes near this
+const MAX_MESSAGE: usize = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Request {
+    Ping {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+    },
+    /// Credentials for the page at `origin`, such as `https://github.com`
+    GetCredentials {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        origin: String,
+    },
+}
+
+impl Request {
+    pub fn id(&self) -> Option<serde_json::Value> {
+        match self {
+            Request::Ping { id } | Request::GetCredentials { id, .. } => id.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Reply {
+    Ok {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+    NotFound,
+    /// User cancelled the confirmation
+    Denied,
+    Error {
+        message: String,
+    },
+}
+
+/// Reply, echoing the request's `id` so the extension can match them up
+#[derive(Debug, Serialize)]
+pub struct Response {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub reply: Reply,
+}
+
+/// Read one length-prefixed message, None once the browser closes the pipe.
+///
+/// Messages that aren't valid requests come back as an error inside, so
+/// the connection can carry on. A message cut short can't be recovered from.
+pub fn read<R: Read>(input: &mut R) -> Result<Option<Result<Request>>> {
+    let mut length = [0u8; 4];
+    let mut filled = 0;
+    while filled < length.len() {
+        match input.read(&mut length[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(Error::msg(format!("Message length cut short after {} bytes", filled))),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let length = u32::from_ne_bytes(length) as usize;
+    if length > MAX_MESSAGE {
+        return Err(Error::msg(format!("Message of {} bytes is too long", length)))
+    }
+    let mut message = vec![0u8; length];
+    input.read_exact(&mut message)
+        .with_context(|| format!("Message of {} bytes cut short", length))?;
+    Ok(Some(serde_json::from_slice(&message).map_err(Error::from)))
+}
+
+pub fn write<W: Write>(output: &mut W, response: &Response) -> Result<()> {
+    let message = serde_json::to_vec(response)?;
+    output.write_all(&(message.len() as u32).to_ne_bytes())?;
+    output.write_all(&message)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed(message: &[u8]) -> Vec<u8> {
+        let mut framed = (message.len() as u32).to_ne_bytes().to_vec();
+        framed.extend_from_slice(message);
+        framed
+    }
+
+    fn read_all(input: &[u8]) -> Result<Option<Result<Request>>> {
+        read(&mut &input[..])
+    }
+
+    #[test]
+    fn reads_requests_one_after_another() {
+        let mut input = framed(br#"{"action":"ping","id":7}"#);
+        input.extend(framed(br#"{"action":"get-credentials","origin":"https://github.com"}"#));
+        let mut input = &input[..];
+
+        let ping = read(&mut input).unwrap().unwrap().unwrap();
+        assert!(matches!(ping, Request::Ping { .. }));
+        assert_eq!(ping.id(), Some(7.into()));
+        match read(&mut input).unwrap().unwrap().unwrap() {
+            Request::GetCredentials { id, origin } => {
+                assert_eq!(id, None);
+                assert_eq!(origin, "https://github.com");
+            },
+            request => panic!("{:?}", request),
+        }
+        assert!(read(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn bad_requests_leave_the_connection_usable() {
+        let mut input = framed(b"not json");
+        input.extend(framed(br#"{"action":"delete-everything"}"#));
+        input.extend(framed(br#"{"action":"ping"}"#));
+        let mut input = &input[..];
+
+        assert!(read(&mut input).unwrap().unwrap().is_err());
+        assert!(read(&mut input).unwrap().unwrap().is_err());
+        assert!(read(&mut input).unwrap().unwrap().is_ok());
+    }
+
+    #[test]
+    fn truncated_length_fails() {
+        let error = read_all(&[5, 0]).unwrap_err();
+        assert!(error.to_string().contains("cut short after 2 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn truncated_message_fails() {
+        let input = framed(br#"{"action":"ping"}"#);
+        let error = read_all(&input[..input.len() - 1]).unwrap_err();
+        assert!(error.to_string().contains("cut short"), "{}", error);
+    }
+
+    #[test]
+    fn oversized_message_fails_before_reading_it() {
+        let input = ((MAX_MESSAGE + 1) as u32).to_ne_bytes();
+        let error = read_all(&input).unwrap_err();
+        assert!(error.to_string().contains("too long"), "{}", error);
+    }
+
+    #[test]
+    fn writes_length_prefixed_responses() {
+        let mut output = vec![];
+        let response = Response { id: Some(1.into()), reply: Reply::Ok { username: Some("alice".to_owned()), password: None } };
+        write(&mut output, &response).unwrap();
+
+        let message = br#"{"id":1,"status":"ok","username":"alice"}"#;
+        assert_eq!(output, framed(message));
+
+        let mut output = vec![];
+        write(&mut output, &Response { id: None, reply: Reply::NotFound }).unwrap();
+        assert_eq!(output, framed(br#"{"status":"not-found"}"#));
+    }
+}
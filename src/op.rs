@@ -1,33 +1,152 @@
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::process::Stdio;
+use std::fmt;
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
 use log::*;
 use serde::{ Serialize, Deserialize};
 use anyhow::{Result, Error, Context};
 use itertools::Itertools;
 
+use crate::commands;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Item {
     pub uuid: String,
+    pub vault_uuid: Option<String>,
+    pub template_uuid: Option<String>,
     pub overview: Overview,
 }
 
+impl Item {
+    /// Only a login's subtitle is its username
+    pub fn username(&self) -> Option<String> {
+        self.overview.subtitle.clone().filter(|_| self.template_uuid.as_deref() == Some("001"))
+    }
+
+    /// Name of the kind of item, such as Login or Secure Note
+    pub fn category(&self) -> Option<&'static str> {
+        Some(match self.template_uuid.as_deref()? {
+            "001" => "Login",
+            "002" => "Credit Card",
+            "003" => "Secure Note",
+            "004" => "Identity",
+            "005" => "Password",
+            "006" => "Document",
+            "100" => "Software License",
+            "101" => "Bank Account",
+            "102" => "Database",
+            "103" => "Driver License",
+            "104" => "Outdoor License",
+            "105" => "Membership",
+            "106" => "Passport",
+            "107" => "Reward Program",
+            "108" => "Social Security Number",
+            "109" => "Wireless Router",
+            "110" => "Server",
+            "111" => "Email Account",
+            "112" => "API Credential",
+            "113" => "Medical Record",
+            "114" => "SSH Key",
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Overview {
     pub title: String,
+    /// Additional info shown under the title: the username for logins, but the start of
+    /// a note or a masked card number for other items
+    #[serde(rename = "ainfo")]
+    pub subtitle: Option<String>,
     pub url: Option<String>,
     pub tags: Option<Vec<String>>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Vault {
+    pub uuid: String,
+    pub name: String,
+}
+
+/// op was killed for taking too long, most likely waiting on a network that's gone
+#[derive(Debug)]
+pub struct Timeout {
+    pub command: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "op {} timed out after {}s", self.command, self.after.as_secs_f32())
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// op couldn't reach 1Password, signing in again won't help
+#[derive(Debug)]
+pub struct Offline {
+    pub message: String,
+}
+
+impl fmt::Display for Offline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "op can't reach 1Password: {}", self.message)
+    }
+}
+
+impl std::error::Error for Offline {}
+
+/// What op (through Go's net/http) says when the network is the problem
+const CONNECTIVITY_ERRORS: &[&str] = &[
+    "dial tcp",
+    "no such host",
+    "connection refused",
+    "connection reset",
+    "network is unreachable",
+    "temporary failure in name resolution",
+    "i/o timeout",
+    "tls handshake timeout",
+    "client.timeout exceeded",
+];
+
+/// Listing and getting items should be quick
+const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Signing in can wait on a slow server, or a second factor
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(120);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Use one timeout for every op command, instead of the defaults
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+fn timeout(default: Duration) -> Duration {
+    TIMEOUT.get().copied().unwrap_or(default)
+}
+
 pub fn get_items(account_uuid: &str, token: &str) -> Result<Vec<Item>> {
-    let items = op("", vec!["list", "items", "--account", account_uuid, "--session", token])?;
+    let items = op("", vec!["list", "items", "--account", account_uuid, "--session", token], timeout(QUERY_TIMEOUT))?;
     // Deserialisation issues should panic
     let items: Vec<Item> = serde_json::from_str(&items)
         .with_context(||"Failed to de-serialise JSON item list")?;
     Ok(items)
 }
 
+pub fn get_vaults(account_uuid: &str, token: &str) -> Result<Vec<Vault>> {
+    let vaults = op("", vec!["list", "vaults", "--account", account_uuid, "--session", token], timeout(QUERY_TIMEOUT))?;
+    let vaults: Vec<Vault> = serde_json::from_str(&vaults)
+        .with_context(||"Failed to de-serialise JSON vault list")?;
+    Ok(vaults)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Credential {
@@ -40,14 +159,45 @@ pub struct Credential {
 pub struct Details {
     pub fields: Option<Vec<Field>>,
     pub password: Option<String>,
+    /// Body of secure notes, and the notes of any other item
+    pub notes_plain: Option<String>,
+    /// Where every item other than logins keeps its data
+    pub sections: Option<Vec<Section>>,
 }
 impl Details {
     pub fn get_fields(self) -> Vec<Field> {
-        self.fields.unwrap_or(self.password.into_iter().map(|password| Field {
-            value: password,
-            designation: "password".to_string(),
-            name: "password".to_string(),
-        }).collect_vec())
+        let mut fields = self.fields.unwrap_or_default();
+        if fields.is_empty() {
+            fields.extend(self.password.into_iter().map(|password| Field {
+                value: password,
+                designation: "password".to_string(),
+                name: "password".to_string(),
+                section: None,
+            }));
+        }
+        // Other items than logins keep their secret in a section, the first concealed field stands in for a password
+        let mut has_password = fields.iter().any(|f| f.designation == "password");
+        for section in self.sections.into_iter().flatten() {
+            let label = section.title.filter(|t| !t.is_empty());
+            fields.extend(section.fields.into_iter().flatten().filter_map(|field| {
+                let value = field.text()?;
+                let is_password = !has_password && field.kind == "concealed";
+                has_password |= is_password;
+                Some(Field {
+                    name: field.label.filter(|l| !l.is_empty()).unwrap_or(field.name),
+                    designation: if is_password { "password".to_string() } else { String::new() },
+                    value,
+                    section: label.clone(),
+                })
+            }));
+        }
+        fields.extend(self.notes_plain.filter(|n| !n.is_empty()).map(|notes| Field {
+            value: notes,
+            designation: "notes".to_string(),
+            name: "notes".to_string(),
+            section: None,
+        }));
+        fields
     }
 }
 
@@ -57,11 +207,57 @@ pub struct Field {
     pub designation: String,
     pub name: String,
     pub value: String,
+    /// Title of the section the field is in, login fields have none
+    #[serde(default)]
+    pub section: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Section {
+    pub title: Option<String>,
+    pub fields: Option<Vec<SectionField>>,
+}
+
+/// Section fields use one letter keys
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectionField {
+    /// Kind of value: string, concealed, email, monthYear, address and so on
+    #[serde(rename = "k", default)]
+    pub kind: String,
+    #[serde(rename = "n", default)]
+    pub name: String,
+    #[serde(rename = "t")]
+    pub label: Option<String>,
+    #[serde(rename = "v")]
+    pub value: Option<serde_json::Value>,
+}
+
+impl SectionField {
+    /// Value as text, None when there is none
+    fn text(&self) -> Option<String> {
+        use serde_json::Value;
+        let text = match self.value.as_ref()? {
+            // Expiry dates such as 202712
+            Value::Number(n) if self.kind == "monthYear" => {
+                let n = n.as_u64()?;
+                format!("{:04}/{:02}", n / 100, n % 100)
+            },
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            // Addresses, in the order they're written
+            Value::Object(parts) => ["street", "city", "state", "zip", "country"].iter()
+                .filter_map(|p| parts.get(*p).and_then(|v| v.as_str()).filter(|v| !v.is_empty()))
+                .join(", "),
+            _ => return None,
+        };
+        Some(text).filter(|t| !t.is_empty())
+    }
 }
 
 pub fn get_credentials(item_uuid: &str, token: &str) -> Result<Credential> {
     // Query op for title / uuid of the item
-    let output = op("", ["get", "item", &item_uuid, "--session", token].to_vec())?;
+    let output = op("", ["get", "item", item_uuid, "--session", token].to_vec(), timeout(QUERY_TIMEOUT))?;
     //debug!("Creds: {}", output);
     let credential: Credential = serde_json::from_str(&output)
         .with_context(||format!("Error de-serialising Credential fields from JSON: {}", &output))?;
@@ -69,19 +265,29 @@ pub fn get_credentials(item_uuid: &str, token: &str) -> Result<Credential> {
     Ok(credential)
 }
 
+/// Current one-time password of an item with a TOTP field
+pub fn get_totp(item_uuid: &str, token: &str) -> Result<String> {
+    let totp = op("", ["get", "totp", item_uuid, "--session", token].to_vec(), timeout(QUERY_TIMEOUT))?;
+    Ok(totp.trim().to_owned())
+}
+
 pub fn login(shorthand: &str, unlock: &str) -> Result<String> {
-    let token = op(&format!("{}\n", unlock), vec!["signin", shorthand, "--output=raw"])?;
+    let token = op(&format!("{}\n", unlock), vec!["signin", shorthand, "--output=raw"], timeout(LOGIN_TIMEOUT))?;
     let token = token.trim().to_owned();
     Ok(token)
 }
 
-pub fn op(input: &str, args: Vec<&str>) -> Result<String> {
+/// End a session, so its token can't be used again
+pub fn logout(shorthand: &str, token: &str) -> Result<()> {
+    op("", vec!["signout", "--account", shorthand, "--session", token], timeout(QUERY_TIMEOUT))?;
+    Ok(())
+}
+
+pub fn op(input: &str, args: Vec<&str>, timeout: Duration) -> Result<String> {
     // Spawn signing, read out pipe for prompt
-    let mut process = Command::new(
-        "op"
-        //"./mock.sh"
-    );
-    process.args(args)
+    // R1PW_OP=./mock.sh to try things out without 1Password
+    let mut process = commands::command("op")?;
+    process.args(&args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
@@ -92,19 +298,54 @@ pub fn op(input: &str, args: Vec<&str>) -> Result<String> {
     // Feed to stdin of op
     stdin.write_all(input.as_bytes())?;
     drop(stdin);
+    // Read output as it comes, op blocks once a pipe fills up
+    let stdout = read_in_background(process.stdout.take().unwrap());
+    let stderr = read_in_background(process.stderr.take().unwrap());
     debug!("Waiting for process to finish");
-    let output = process.wait_with_output()?;
-    if ! output.status.success() {
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = process.try_wait()? {
+            break status
+        }
+        if Instant::now() >= deadline {
+            warn!("Killing op after {:?}", timeout);
+            let _ = process.kill();
+            let _ = process.wait();
+            return Err(Timeout {
+                command: args.iter().take_while(|a| !a.starts_with('-')).join(" "),
+                after: timeout,
+            }.into())
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    let offline = !status.success() && {
+        let stderr = String::from_utf8_lossy(&stderr).to_lowercase();
+        CONNECTIVITY_ERRORS.iter().any(|e| stderr.contains(e))
+    };
+    if offline {
+        return Err(Offline { message: String::from_utf8_lossy(&stderr).trim().to_owned() }.into())
+    }
+    if ! status.success() {
         return Err(Error::msg(format!(
             "{}: {}",
-            match output.status.code() {
+            match status.code() {
                 None => "op command terminated by signal".to_owned(),
                 Some(c) => format!("op command failed with exit code {}", c),
-            }, String::from_utf8_lossy(&output.stderr).trim())
+            }, String::from_utf8_lossy(&stderr).trim())
         ));
     }
     debug!("Done waiting.");
     // read from stdout
-    let output = String::from_utf8_lossy(&output.stdout).into_owned();
+    let output = String::from_utf8_lossy(&stdout).into_owned();
     Ok(output)
 }
+
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = vec![];
+        let _ = pipe.read_to_end(&mut output);
+        output
+    })
+}
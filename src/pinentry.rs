@@ -0,0 +1,173 @@
+use std::io::{BufRead, Write};
+use anyhow::Result;
+use log::{debug, warn};
+
+use crate::model::config::PinentryRule;
+
+// libgpg-error codes, with the pinentry error source
+const ERR_CANCELED: &str = "ERR 83886179 Operation cancelled <Pinentry>";
+const ERR_NOT_CONFIRMED: &str = "ERR 83886194 Not confirmed <Pinentry>";
+const ERR_UNKNOWN_COMMAND: &str = "ERR 83886355 Unknown IPC command <Pinentry>";
+const ERR_GENERAL: u32 = 83886081;
+
+/// Everything gpg-agent has told us about the passphrase it wants
+#[derive(Debug, Default)]
+pub struct State {
+    pub description: Option<String>,
+    pub prompt: Option<String>,
+    pub title: Option<String>,
+    pub keygrip: Option<String>,
+    /// Set when a previous answer was wrong
+    pub error: Option<String>,
+}
+
+impl State {
+    /// One line summary for the launcher prompt
+    pub fn summary(&self) -> String {
+        self.description.as_ref()
+            .or(self.prompt.as_ref())
+            .or(self.title.as_ref())
+            .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_else(|| "Passphrase:".to_owned())
+    }
+}
+
+/// A rule matches when everything it specifies matches
+pub fn matches(rule: &PinentryRule, state: &State) -> bool {
+    if rule.keygrip.is_none() && rule.description.is_none() {
+        return false
+    }
+    let keygrip = rule.keygrip.as_ref().is_none_or(|k|
+        state.keygrip.as_ref().is_some_and(|grip| grip.eq_ignore_ascii_case(k))
+    );
+    let description = rule.description.as_ref().is_none_or(|d|
+        state.description.as_ref().is_some_and(|desc| desc.contains(d.as_str()))
+    );
+    keygrip && description
+}
+
+/// Speak the Assuan pinentry protocol until BYE or the end of input.
+///
+/// `getpin` answers GETPIN, None meaning cancelled, and `confirm` answers CONFIRM.
+pub fn serve<R, W, P, C>(input: R, mut output: W, mut getpin: P, mut confirm: C) -> Result<()>
+where
+    R: BufRead,
+    W: Write,
+    P: FnMut(&State) -> Result<Option<String>>,
+    C: FnMut(&State) -> Result<bool>,
+{
+    let mut state = State::default();
+    writeln!(output, "OK Pleased to meet you")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, Some(decode(argument))),
+            None => (line.as_str(), None),
+        };
+        debug!("Pinentry command {}", command);
+        let response: Vec<String> = match command.to_uppercase().as_str() {
+            "SETDESC" => set(&mut state.description, argument),
+            "SETPROMPT" => set(&mut state.prompt, argument),
+            "SETTITLE" => set(&mut state.title, argument),
+            "SETERROR" => set(&mut state.error, argument),
+            // Given as `n/<keygrip>`, or `--clear`
+            "SETKEYINFO" => set(&mut state.keygrip, argument
+                .filter(|a| a != "--clear")
+                .map(|a| a.split_once('/').map(|(_, grip)| grip.to_owned()).unwrap_or(a))),
+            // gpg-agent is told about failures, it may well ask again
+            "GETPIN" => match getpin(&state) {
+                Ok(Some(pin)) => {
+                    state.error = None;
+                    vec![format!("D {}", encode(&pin)), "OK".to_owned()]
+                },
+                Ok(None) => {
+                    state.error = None;
+                    vec![ERR_CANCELED.to_owned()]
+                },
+                Err(e) => failed(e),
+            },
+            "CONFIRM" if argument.as_deref() == Some("--one-button") => ok(),
+            "CONFIRM" => match confirm(&state) {
+                Ok(true) => ok(),
+                Ok(false) => vec![ERR_NOT_CONFIRMED.to_owned()],
+                Err(e) => failed(e),
+            },
+            "GETINFO" => match argument.as_deref() {
+                Some("flavor") => vec!["D r1pw".to_owned(), "OK".to_owned()],
+                Some("version") => vec![format!("D {}", env!("CARGO_PKG_VERSION")), "OK".to_owned()],
+                Some("pid") => vec![format!("D {}", std::process::id()), "OK".to_owned()],
+                _ => ok(),
+            },
+            "RESET" => {
+                state = State::default();
+                ok()
+            },
+            "BYE" => {
+                writeln!(output, "OK closing connection")?;
+                output.flush()?;
+                return Ok(())
+            },
+            // Settings and messages that don't change the answer
+            "OPTION" | "MESSAGE" | "NOP" | "SETOK" | "SETCANCEL" | "SETNOTOK" | "SETREPEAT"
+            | "SETREPEATERROR" | "SETQUALITYBAR" | "SETQUALITYBAR_TT" | "SETGENPIN"
+            | "SETGENPIN_TT" | "SETTIMEOUT" => ok(),
+            _ => vec![ERR_UNKNOWN_COMMAND.to_owned()],
+        };
+        for line in response {
+            writeln!(output, "{}", line)?;
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn ok() -> Vec<String> {
+    vec!["OK".to_owned()]
+}
+
+fn failed(error: anyhow::Error) -> Vec<String> {
+    warn!("Unable to answer pinentry: {:#}", error);
+    let message = format!("{:#}", error).split_whitespace().collect::<Vec<_>>().join(" ");
+    vec![format!("ERR {} {} <Pinentry>", ERR_GENERAL, message)]
+}
+
+fn set(field: &mut Option<String>, value: Option<String>) -> Vec<String> {
+    *field = value;
+    ok()
+}
+
+/// Undo Assuan's `%XX` escaping
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escape what can't appear in a data line
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '%' | '\r' | '\n' | '\\' => encoded.push_str(&format!("%{:02X}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,102 @@
+use anyhow::{Result, Error};
+
+use crate::model::logical;
+
+pub const SCHEME: &str = "r1pw://";
+
+/// Points at a single field of an item, written as `<item>/<field>`
+/// or `r1pw://<account>/<item>/<field>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub account: Option<String>,
+    pub item: String,
+    pub field: String,
+}
+
+/// Parse a reference, the item being a name or UUID.
+///
+/// Item names may themselves contain slashes, the field is whatever follows the last one.
+pub fn parse(reference: &str) -> Result<Reference> {
+    let reference = reference.trim();
+    let (account, rest) = match reference.strip_prefix(SCHEME) {
+        Some(rest) => match rest.split_once('/') {
+            Some((account, rest)) if !account.is_empty() => (Some(account.to_owned()), rest),
+            _ => return Err(Error::msg(format!("Bad reference {}, expected {}<account>/<item>/<field>", reference, SCHEME))),
+        },
+        None => (None, reference),
+    };
+    match rest.rsplit_once('/') {
+        Some((item, field)) if !item.is_empty() && !field.is_empty() => Ok(Reference {
+            account,
+            item: item.to_owned(),
+            field: field.to_owned(),
+        }),
+        _ => Err(Error::msg(format!("Bad reference {}, expected <item>/<field>", reference))),
+    }
+}
+
+/// Parse `NAME=<item>/<field>`
+pub fn parse_assignment(assignment: &str) -> Result<(String, Reference)> {
+    match assignment.split_once('=') {
+        Some((name, reference)) if !name.trim().is_empty() =>
+            Ok((name.trim().to_owned(), parse(reference)?)),
+        _ => Err(Error::msg(format!("Bad variable {}, expected NAME=<item>/<field>", assignment))),
+    }
+}
+
+/// Parse an env file of `NAME=<item>/<field>` lines, skipping blanks and `#` comments
+pub fn parse_env_file(contents: &str) -> Result<Vec<(String, Reference)>> {
+    contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_assignment(line.strip_prefix("export ").unwrap_or(line)))
+        .collect()
+}
+
+/// Why an item lookup found no single item
+#[derive(Debug)]
+pub enum LookupError {
+    NotFound(String),
+    /// Several items share the name
+    Ambiguous(String, usize),
+}
+
+impl std::fmt::Display for LookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LookupError::NotFound(name) => write!(f, "No item called {}", name),
+            LookupError::Ambiguous(name, count) => write!(
+                f, "{} items are called {}, use a UUID or narrow by --account or --vault", count, name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+/// Find the one item a reference points at
+pub fn resolve<'a>(items: &[&'a logical::Item], reference: &Reference) -> Result<&'a logical::Item> {
+    find(items, reference.account.as_deref(), &reference.item).map_err(|e| match (&e, &reference.account) {
+        (LookupError::NotFound(_), Some(account)) =>
+            Error::msg(format!("No item called {} in account {}", reference.item, account)),
+        _ => e.into(),
+    })
+}
+
+/// Find the one item with a UUID, or a name ignoring case, optionally in one account
+pub fn find<'a>(items: &[&'a logical::Item], account: Option<&str>, item: &str) -> std::result::Result<&'a logical::Item, LookupError> {
+    let items = items.iter()
+        .filter(|i| account.is_none_or(|a| a.eq_ignore_ascii_case(&i.account_name)))
+        .collect::<Vec<_>>();
+    if let Some(found) = items.iter().find(|i| i.uuid == item) {
+        return Ok(found)
+    }
+    let named = items.iter()
+        .filter(|i| i.name.eq_ignore_ascii_case(item))
+        .collect::<Vec<_>>();
+    match named.as_slice() {
+        [found] => Ok(found),
+        [] => Err(LookupError::NotFound(item.to_owned())),
+        _ => Err(LookupError::Ambiguous(item.to_owned(), named.len())),
+    }
+}
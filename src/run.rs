@@ -0,0 +1,166 @@
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, Stdio};
+use std::io::{self, Read, Write};
+use std::thread;
+use anyhow::{Result, Error, Context};
+
+const MASK: &[u8] = b"<concealed by r1pw>";
+
+fn command(args: &[String], env: &[(String, String)]) -> Command {
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..])
+        .envs(env.iter().map(|(name, value)| (name, value)));
+    command
+}
+
+/// Replace r1pw with the command, so nothing is left running holding the secrets
+pub fn exec(args: &[String], env: &[(String, String)]) -> Result<()> {
+    let error = command(args, env).exec();
+    Err(Error::from(error).context(format!("Failed to run {}", args[0])))
+}
+
+/// Run the command with secret values hidden in its output, returning its exit code.
+///
+/// Output is passed on as it's read, less any tail that could be the start
+/// of a secret, so one split across two reads is still hidden.
+pub fn run_masked(args: &[String], env: &[(String, String)]) -> Result<i32> {
+    let mut secrets = env.iter()
+        .map(|(_, value)| value.clone())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+    // Longest first, so a secret containing another is hidden whole
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+    let mut child = command(args, env)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(||format!("Failed to run {}", args[0]))?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let out_secrets = secrets.clone();
+    let out = thread::spawn(move || copy_masked(stdout, io::stdout(), &out_secrets));
+    let err = thread::spawn(move || copy_masked(stderr, io::stderr(), &secrets));
+    let status = child.wait()?;
+    // Broken pipes downstream are the reader's business, not a reason to fail
+    let _ = out.join();
+    let _ = err.join();
+    Ok(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+fn copy_masked<R: Read, W: Write>(mut from: R, mut to: W, secrets: &[String]) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+    let mut pending = vec![];
+    loop {
+        let read = match from.read(&mut buffer) {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buffer[..read]);
+        let (masked, consumed) = mask(&pending, secrets, read == 0);
+        to.write_all(&masked)?;
+        to.flush()?;
+        pending.drain(..consumed);
+        if read == 0 {
+            return Ok(())
+        }
+    }
+}
+
+/// Mask the secrets in what's been read so far, returning the output and how much of the input it covers.
+///
+/// Unless this is the end, a tail that could be the start of a secret is held back for the next chunk,
+/// so it's never longer than the longest secret less a byte.
+fn mask(chunk: &[u8], secrets: &[String], end: bool) -> (Vec<u8>, usize) {
+    let mut masked = Vec::with_capacity(chunk.len());
+    let mut i = 0;
+    'scan: while i < chunk.len() {
+        let rest = &chunk[i..];
+        if !end && secrets.iter().any(|s| s.len() > rest.len() && s.as_bytes().starts_with(rest)) {
+            break
+        }
+        for secret in secrets {
+            if rest.starts_with(secret.as_bytes()) {
+                masked.extend_from_slice(MASK);
+                i += secret.len();
+                continue 'scan;
+            }
+        }
+        masked.push(chunk[i]);
+        i += 1;
+    }
+    (masked, i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// Hands out one chunk per read, noting what had been written before each
+    struct Chunks {
+        chunks: VecDeque<&'static [u8]>,
+        written: Rc<RefCell<Vec<u8>>>,
+        seen: Vec<String>,
+    }
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.seen.push(String::from_utf8(self.written.borrow().clone()).unwrap());
+            let chunk = self.chunks.pop_front().unwrap_or_default();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn copy(chunks: &[&'static [u8]], secrets: &[&str]) -> (String, Vec<String>) {
+        let written = Rc::new(RefCell::new(vec![]));
+        let mut reader = Chunks { chunks: chunks.iter().copied().collect(), written: written.clone(), seen: vec![] };
+        let secrets = secrets.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        copy_masked(&mut reader, Shared(written.clone()), &secrets).unwrap();
+        let output = String::from_utf8(written.borrow().clone()).unwrap();
+        (output, reader.seen)
+    }
+
+    #[test]
+    fn secrets_split_across_reads_are_masked() {
+        let (output, _) = copy(&[b"token=hun", b"ter2\n"], &["hunter2"]);
+        assert_eq!(output, "token=<concealed by r1pw>\n");
+    }
+
+    #[test]
+    fn output_without_a_newline_is_passed_on() {
+        let (output, seen) = copy(&[b"Password: ", b"ok\n"], &["hunter2"]);
+        assert_eq!(seen[1], "Password: ");
+        assert_eq!(output, "Password: ok\n");
+    }
+
+    #[test]
+    fn the_start_of_a_secret_waits_for_the_next_read() {
+        let (output, seen) = copy(&[b"key: hun", b"gry\n"], &["hunter2"]);
+        assert_eq!(seen[1], "key: ");
+        assert_eq!(output, "key: hungry\n");
+    }
+
+    #[test]
+    fn longer_secrets_win() {
+        let (output, _) = copy(&[b"abc", b"def abc\n"], &["abcdef", "abc"]);
+        assert_eq!(output, "<concealed by r1pw> <concealed by r1pw>\n");
+    }
+}
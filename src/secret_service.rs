@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use anyhow::{Result, Context};
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+use zbus::{interface, fdo, DBusError, ObjectServer};
+use zbus::message::Header;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Type, Value};
+
+use crate::model::logical;
+use crate::url_match;
+
+const BUS_NAME: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+/// Only unencrypted sessions are offered, they never leave the user's own bus
+const ALGORITHM: &str = "plain";
+
+/// Looks up an item's secret by UUID, called when a client asks for it
+pub type Fetch = Box<dyn FnMut(&str) -> Result<String> + Send>;
+
+/// Cached item as seen over D-Bus
+#[derive(Debug)]
+pub struct Entry {
+    pub uuid: String,
+    pub label: String,
+    pub account: String,
+    pub attributes: HashMap<String, String>,
+}
+
+impl From<&logical::Item> for Entry {
+    fn from(item: &logical::Item) -> Self {
+        let mut attributes = HashMap::new();
+        attributes.insert("uuid".to_owned(), item.uuid.clone());
+        attributes.insert("account".to_owned(), item.account_name.clone());
+        if let Some(vault) = &item.vault_name {
+            attributes.insert("vault".to_owned(), vault.clone());
+        }
+        attributes.insert("title".to_owned(), item.name.clone());
+        if let Some(username) = &item.username {
+            attributes.insert("username".to_owned(), username.clone());
+            // Attribute names of libsecret's network password schema
+            attributes.insert("user".to_owned(), username.clone());
+        }
+        if let Some(url) = &item.url {
+            attributes.insert("url".to_owned(), url.clone());
+            if let Some(host) = url_match::hostname(url) {
+                attributes.insert("server".to_owned(), host);
+            }
+            if let Some((protocol, _)) = url.split_once("://") {
+                attributes.insert("protocol".to_owned(), protocol.to_lowercase());
+            }
+        }
+        if !item.tags.is_empty() {
+            attributes.insert("tags".to_owned(), item.tags.join(","));
+        }
+        Entry {
+            uuid: item.uuid.clone(),
+            label: item.name.clone(),
+            account: item.account_name.clone(),
+            attributes,
+        }
+    }
+}
+
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "org.freedesktop.Secret.Error")]
+enum SecretError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    NoSession(String),
+    NoSuchObject(String),
+}
+
+/// Secret as passed over D-Bus, `(oayays)`
+#[derive(Debug, Serialize, Deserialize, Type)]
+struct Secret {
+    session: OwnedObjectPath,
+    parameters: Vec<u8>,
+    value: Vec<u8>,
+    content_type: String,
+}
+
+struct Shared {
+    entries: Vec<Entry>,
+    /// Account shorthands, one collection each
+    accounts: Vec<String>,
+    fetch: Mutex<Fetch>,
+    sessions: Mutex<Vec<String>>,
+    next_session: AtomicUsize,
+}
+
+impl Shared {
+    fn entry(&self, path: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| item_path(e) == path)
+    }
+
+    fn search<'a>(&'a self, account: Option<&'a str>, attributes: &'a HashMap<String, String>) -> impl Iterator<Item = &'a Entry> {
+        self.entries.iter()
+            .filter(move |e| account.is_none_or(|a| e.account == a))
+            .filter(move |e| attributes.iter().all(|(k, v)| e.attributes.get(k) == Some(v)))
+    }
+
+    fn secret(&self, entry: &Entry, session: OwnedObjectPath) -> Result<Secret, SecretError> {
+        if !self.sessions.lock().unwrap().contains(&session.to_string()) {
+            return Err(SecretError::NoSession(format!("No session {}", session.as_str())))
+        }
+        let mut fetch = self.fetch.lock().unwrap();
+        let value = fetch(&entry.uuid).map_err(|e| {
+            warn!("Unable to fetch secret for {}: {:#}", entry.label, e);
+            SecretError::ZBus(zbus::Error::Failure(format!("Unable to fetch secret: {}", e)))
+        })?;
+        Ok(Secret {
+            session,
+            parameters: vec![],
+            value: value.into_bytes(),
+            content_type: "text/plain; charset=utf8".to_owned(),
+        })
+    }
+}
+
+// Object paths may only contain [A-Za-z0-9_]
+fn sanitise(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn collection_path(account: &str) -> String {
+    format!("{}/collection/{}", SERVICE_PATH, sanitise(account))
+}
+
+fn item_path(entry: &Entry) -> String {
+    format!("{}/{}", collection_path(&entry.account), sanitise(&entry.uuid))
+}
+
+fn object_path(path: String) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(path).expect("sanitised object path")
+}
+
+fn no_prompt() -> OwnedObjectPath {
+    object_path("/".to_owned())
+}
+
+fn read_only() -> fdo::Error {
+    fdo::Error::NotSupported("r1pw is read-only, change items in 1Password instead".to_owned())
+}
+
+struct Service {
+    shared: Arc<Shared>,
+}
+
+#[interface(name = "org.freedesktop.Secret.Service")]
+impl Service {
+    async fn open_session(
+        &self,
+        algorithm: &str,
+        _input: Value<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<(OwnedValue, OwnedObjectPath)> {
+        if algorithm != ALGORITHM {
+            // libsecret falls back to plain when its preferred algorithm isn't supported
+            return Err(fdo::Error::NotSupported(format!("Algorithm {} is not supported", algorithm)))
+        }
+        let id = self.shared.next_session.fetch_add(1, Ordering::SeqCst);
+        let path = format!("{}/session/s{}", SERVICE_PATH, id);
+        server.at(path.as_str(), Session { shared: self.shared.clone() }).await?;
+        self.shared.sessions.lock().unwrap().push(path.clone());
+        debug!("Opened session {}", path);
+        let output = OwnedValue::try_from(Value::from(""))
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        Ok((output, object_path(path)))
+    }
+
+    fn create_collection(&self, _properties: HashMap<String, OwnedValue>, _alias: &str) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        Err(read_only())
+    }
+
+    fn search_items(&self, attributes: HashMap<String, String>) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+        let unlocked = self.shared.search(None, &attributes)
+            .map(|e| object_path(item_path(e)))
+            .collect();
+        (unlocked, vec![])
+    }
+
+    // Everything is always unlocked, op is asked for a secret only when it is read
+    fn unlock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+        (objects, no_prompt())
+    }
+
+    fn lock(&self, _objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+        (vec![], no_prompt())
+    }
+
+    fn get_secrets(&self, items: Vec<OwnedObjectPath>, session: OwnedObjectPath) -> Result<HashMap<OwnedObjectPath, Secret>, SecretError> {
+        let mut secrets = HashMap::new();
+        for path in items {
+            if let Some(entry) = self.shared.entry(path.as_str()) {
+                let secret = self.shared.secret(entry, session.clone())?;
+                secrets.insert(path, secret);
+            }
+        }
+        Ok(secrets)
+    }
+
+    fn read_alias(&self, name: &str) -> OwnedObjectPath {
+        match (name, self.shared.accounts.first()) {
+            ("default", Some(account)) => object_path(collection_path(account)),
+            _ => no_prompt(),
+        }
+    }
+
+    fn set_alias(&self, _name: &str, _collection: OwnedObjectPath) -> fdo::Result<()> {
+        Err(read_only())
+    }
+
+    #[zbus(property)]
+    fn collections(&self) -> Vec<OwnedObjectPath> {
+        self.shared.accounts.iter()
+            .map(|a| object_path(collection_path(a)))
+            .collect()
+    }
+}
+
+struct Collection {
+    account: String,
+    shared: Arc<Shared>,
+}
+
+#[interface(name = "org.freedesktop.Secret.Collection")]
+impl Collection {
+    fn delete(&self) -> fdo::Result<OwnedObjectPath> {
+        Err(read_only())
+    }
+
+    fn search_items(&self, attributes: HashMap<String, String>) -> Vec<OwnedObjectPath> {
+        self.shared.search(Some(&self.account), &attributes)
+            .map(|e| object_path(item_path(e)))
+            .collect()
+    }
+
+    fn create_item(&self, _properties: HashMap<String, OwnedValue>, _secret: Secret, _replace: bool) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        Err(read_only())
+    }
+
+    #[zbus(property)]
+    fn items(&self) -> Vec<OwnedObjectPath> {
+        self.search_items(HashMap::new())
+    }
+
+    #[zbus(property)]
+    fn label(&self) -> String {
+        self.account.clone()
+    }
+
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn created(&self) -> u64 {
+        0
+    }
+
+    #[zbus(property)]
+    fn modified(&self) -> u64 {
+        0
+    }
+}
+
+struct Item {
+    index: usize,
+    shared: Arc<Shared>,
+}
+
+impl Item {
+    fn entry(&self) -> &Entry {
+        &self.shared.entries[self.index]
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Item")]
+impl Item {
+    fn delete(&self) -> fdo::Result<OwnedObjectPath> {
+        Err(read_only())
+    }
+
+    fn get_secret(&self, session: OwnedObjectPath) -> Result<Secret, SecretError> {
+        self.shared.secret(self.entry(), session)
+    }
+
+    fn set_secret(&self, _secret: Secret) -> fdo::Result<()> {
+        Err(read_only())
+    }
+
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn attributes(&self) -> HashMap<String, String> {
+        self.entry().attributes.clone()
+    }
+
+    #[zbus(property)]
+    fn label(&self) -> String {
+        self.entry().label.clone()
+    }
+
+    #[zbus(property)]
+    fn created(&self) -> u64 {
+        0
+    }
+
+    #[zbus(property)]
+    fn modified(&self) -> u64 {
+        0
+    }
+}
+
+struct Session {
+    shared: Arc<Shared>,
+}
+
+#[interface(name = "org.freedesktop.Secret.Session")]
+impl Session {
+    async fn close(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<(), SecretError> {
+        let path = header.path()
+            .ok_or_else(|| SecretError::NoSuchObject("Session without a path".to_owned()))?
+            .to_owned();
+        self.shared.sessions.lock().unwrap().retain(|s| s.as_str() != path.as_str());
+        server.remove::<Session, _>(path).await?;
+        Ok(())
+    }
+}
+
+/// Serve the entries on the session bus until killed
+pub fn serve(entries: Vec<Entry>, fetch: Fetch) -> Result<()> {
+    let mut accounts: Vec<String> = vec![];
+    for entry in &entries {
+        if !accounts.contains(&entry.account) {
+            accounts.push(entry.account.clone());
+        }
+    }
+    let shared = Arc::new(Shared {
+        entries,
+        accounts,
+        fetch: Mutex::new(fetch),
+        sessions: Mutex::new(vec![]),
+        next_session: AtomicUsize::new(0),
+    });
+
+    let mut builder = zbus::blocking::connection::Builder::session()?
+        .serve_at(SERVICE_PATH, Service { shared: shared.clone() })?;
+    for account in &shared.accounts {
+        builder = builder.serve_at(collection_path(account), Collection {
+            account: account.clone(),
+            shared: shared.clone(),
+        })?;
+    }
+    for (index, entry) in shared.entries.iter().enumerate() {
+        builder = builder.serve_at(item_path(entry), Item { index, shared: shared.clone() })?;
+    }
+    let _connection = builder.name(BUS_NAME)?
+        .build()
+        .with_context(|| format!("Unable to own {}, is another Secret Service provider running?", BUS_NAME))?;
+    debug!("Serving {} items as {}", shared.entries.len(), BUS_NAME);
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::{Result, Error};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use log::{info, warn};
+
+use crate::cache;
+use crate::op;
+use crate::value_cache;
+
+/// When the tokens held in memory were read from the cache, or forgotten
+static TOKENS_READ: Mutex<Option<SystemTime>> = Mutex::new(None);
+
+/// Note that the tokens held from now on are newer than any lock so far
+pub fn tokens_read() {
+    *TOKENS_READ.lock().unwrap() = Some(SystemTime::now());
+}
+
+/// Whether a lock dropped the tokens after they were read, so writing them back would undo it
+pub fn tokens_locked() -> bool {
+    TOKENS_READ.lock().unwrap().is_some_and(cache::locked_since)
+}
+
+/// Drop every cached token and value, so the next use must unlock again
+pub fn lock() -> Result<()> {
+    let dropped = cache::drop_tokens(None)?;
+    value_cache::clear()?;
+    info!("Locked {} account(s)", dropped.len());
+    Ok(())
+}
+
+/// Lock, and also end the sessions with op, for one account or all of them
+pub fn logout(shorthand: Option<String>) -> Result<()> {
+    let shorthands = shorthand.map(|s| vec![s]);
+    let dropped = cache::drop_tokens(shorthands.as_deref())?;
+    if let (Some(shorthands), true) = (&shorthands, dropped.is_empty()) {
+        warn!("No session to end for {}", shorthands.join(", "));
+    }
+    value_cache::clear()?;
+    let mut failed = vec![];
+    for account in dropped {
+        match cache::note_offline(op::logout(&account.shorthand, account.token.as_deref().unwrap_or_default())) {
+            Ok(()) => {},
+            // Still signed in as far as 1Password knows
+            Err(e) if e.is::<op::Offline>() || e.is::<op::Timeout>() => {
+                warn!("Unable to sign out of {}: {:#}", account.shorthand, e);
+                failed.push(account.shorthand);
+            },
+            // Most likely the session had expired already
+            Err(e) => warn!("op signout for {} failed: {:#}", account.shorthand, e),
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::msg(format!("Tokens dropped, but op could not sign out of {}", failed.join(", "))))
+    }
+}
+
+/// Notices `lock` running in another process, for servers that hold tokens for long
+pub struct LockCheck {
+    checked: SystemTime,
+}
+
+impl LockCheck {
+    pub fn new() -> Self {
+        LockCheck { checked: SystemTime::now() }
+    }
+
+    /// Whether the tokens were dropped since the last check
+    pub fn locked(&mut self) -> bool {
+        // Taken before looking, so a lock in between is seen next time
+        let now = SystemTime::now();
+        let locked = cache::locked_since(self.checked);
+        self.checked = now;
+        locked
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::reference;
+
+/// Where a placeholder sits in a template
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}", self.line)
+    }
+}
+
+#[derive(Debug)]
+pub struct Placeholder {
+    pub location: Location,
+    /// Byte range of the whole `{{ ... }}`
+    pub range: Range<usize>,
+    /// The reference inside the braces
+    pub text: String,
+}
+
+/// Find every `{{ r1pw://... }}` placeholder, leaving other `{{ }}` for whatever else reads the file
+pub fn placeholders(template: &str) -> Vec<Placeholder> {
+    let mut found = vec![];
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{").map(|s| s + offset) {
+        let end = match template[start..].find("}}") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+        let text = template[start + 2..end - 2].trim();
+        if text.starts_with(reference::SCHEME) {
+            found.push(Placeholder {
+                location: Location { line: template[..start].matches('\n').count() + 1 },
+                range: start..end,
+                text: text.to_owned(),
+            });
+            offset = end;
+        } else {
+            offset = start + 2;
+        }
+    }
+    found
+}
+
+/// Replace placeholders with their values, given in the same order
+pub fn render(template: &str, placeholders: &[Placeholder], values: &[String]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut offset = 0;
+    for (placeholder, value) in placeholders.iter().zip(values) {
+        rendered.push_str(&template[offset..placeholder.range.start]);
+        rendered.push_str(value);
+        offset = placeholder.range.end;
+    }
+    rendered.push_str(&template[offset..]);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(template: &str) -> Vec<(usize, String)> {
+        placeholders(template).into_iter().map(|p| (p.location.line, p.text)).collect()
+    }
+
+    #[test]
+    fn finds_references_with_their_lines() {
+        let template = "user: {{ r1pw://my/DB/username }}\n\npass: {{r1pw://my/DB/password}}\n";
+        assert_eq!(texts(template), [
+            (1, "r1pw://my/DB/username".to_owned()),
+            (3, "r1pw://my/DB/password".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn other_braces_are_left_alone() {
+        assert!(texts("{{ .Values.password }} and {{ env://HOME }}").is_empty());
+        // An opening brace that isn't a reference doesn't hide one after it
+        assert_eq!(texts("{{ {{ r1pw://my/DB/password }}"), [(1, "r1pw://my/DB/password".to_owned())]);
+        assert!(texts("left open {{ r1pw://my/DB/password").is_empty());
+    }
+
+    #[test]
+    fn renders_values_in_place() {
+        let template = "a={{ r1pw://my/A/password }}, {{ keep }}, b={{ r1pw://my/B/password }}\n";
+        let found = placeholders(template);
+        let rendered = render(template, &found, &["1".to_owned(), "2".to_owned()]);
+        assert_eq!(rendered, "a=1, {{ keep }}, b=2\n");
+    }
+
+    #[test]
+    fn values_are_not_read_as_templates() {
+        let template = "{{ r1pw://my/A/password }}";
+        let values = ["{{ r1pw://my/B/password }}".to_owned()];
+        assert_eq!(render(template, &placeholders(template), &values), values[0]);
+    }
+}
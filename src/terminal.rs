@@ -0,0 +1,232 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use anyhow::Result;
+use crossterm::{execute, queue};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+
+use crate::clipboard;
+use crate::fuzzy;
+use crate::launcher::{Launcher, Menu, Choice, Shortcut};
+
+/// Narrower terminals only get the list
+const PREVIEW_MIN_WIDTH: u16 = 60;
+const HELP: &str = "Enter choose  ^U username  ^P password  ^T TOTP  Esc cancel";
+
+/// Full screen picker drawn on the controlling terminal, for when there is no display for dmenu.
+///
+/// It draws on `/dev/tty` rather than stdout, which belongs to whoever asked r1pw for a secret.
+pub struct Terminal;
+
+impl Launcher for Terminal {
+    fn select(&self, menu: &Menu, while_waiting: Box<dyn FnOnce() -> Result<()> + '_>) -> io::Result<Option<Choice>> {
+        let mut screen = Screen::open()?;
+        let mut picker = Picker::new(menu);
+        picker.draw(&mut screen.tty)?;
+        // Keys pressed meanwhile wait in the terminal's buffer
+        while_waiting().map_err(|e| io::Error::other(format!("{:#}", e)))?;
+        loop {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => {
+                    picker.draw(&mut screen.tty)?;
+                    continue
+                },
+            };
+            if let Some(result) = picker.press(key) {
+                return Ok(result)
+            }
+            picker.draw(&mut screen.tty)?;
+        }
+    }
+
+    fn prompt_hidden(&self, prompt: &str) -> io::Result<Option<String>> {
+        let mut screen = Screen::open()?;
+        let mut secret = String::new();
+        loop {
+            let (width, _) = terminal::size()?;
+            let line = format!("{} {}", prompt, "*".repeat(secret.chars().count()));
+            queue!(screen.tty, Clear(ClearType::All), MoveTo(0, 0), Print(truncate(&line, width as usize)))?;
+            screen.tty.flush()?;
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            match (key.code, key.modifiers.contains(KeyModifiers::CONTROL)) {
+                (KeyCode::Enter, _) => return Ok(Some(secret)),
+                (KeyCode::Esc, _) | (KeyCode::Char('c'), true) => return Ok(None),
+                (KeyCode::Char('u'), true) => secret.clear(),
+                (KeyCode::Backspace, _) => {
+                    secret.pop();
+                },
+                (KeyCode::Char(c), false) => secret.push(c),
+                _ => {},
+            }
+        }
+    }
+
+    // Without a display there is no X clipboard for xsel, ask the terminal itself to hold the value
+    fn copy(&self, value: &str) -> Result<()> {
+        let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+        tty.write_all(clipboard::osc52(value).as_bytes())?;
+        tty.flush()?;
+        Ok(())
+    }
+}
+
+/// Raw mode on the alternate screen, put back however the picker exits
+struct Screen {
+    tty: File,
+}
+
+impl Screen {
+    fn open() -> io::Result<Self> {
+        let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+        terminal::enable_raw_mode()?;
+        execute!(tty, EnterAlternateScreen, Hide)?;
+        Ok(Screen { tty })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.tty, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Picker<'a> {
+    menu: &'a Menu<'a>,
+    query: String,
+    /// Indices of the entries matching the query
+    visible: Vec<usize>,
+    selected: usize,
+    /// First visible entry shown, scrolling the list
+    offset: usize,
+}
+
+impl<'a> Picker<'a> {
+    fn new(menu: &'a Menu<'a>) -> Self {
+        let mut picker = Picker {
+            menu,
+            query: String::new(),
+            visible: vec![],
+            selected: 0,
+            offset: 0,
+        };
+        picker.filter();
+        picker
+    }
+
+    fn filter(&mut self) {
+        let menu = self.menu;
+        let mut scored = (0..menu.entries.len())
+            .filter_map(|i| line_score(&self.query, &menu.entries[i].line).map(|s| (i, s)))
+            .collect::<Vec<_>>();
+        // Ties keep the order given, which is already ranked
+        scored.sort_by_key(|&(_, s)| -s);
+        self.visible = scored.into_iter().map(|(i, _)| i).collect();
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    fn current(&self) -> Option<&'a str> {
+        let menu = self.menu;
+        self.visible.get(self.selected).map(|&i| menu.entries[i].line.as_str())
+    }
+
+    /// Handle a key, Some once the picker is done
+    fn press(&mut self, key: KeyEvent) -> Option<Option<Choice>> {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let shortcut = match (key.code, control) {
+            (KeyCode::Char('u'), true) => Some(Shortcut::Username),
+            (KeyCode::Char('p'), true) => Some(Shortcut::Password),
+            (KeyCode::Char('t'), true) => Some(Shortcut::Totp),
+            _ => None,
+        };
+        if let (Some(shortcut), true, Some(line)) = (shortcut, self.menu.shortcuts, self.current()) {
+            return Some(Some(Choice::Shortcut(line.to_owned(), shortcut)))
+        }
+        let last = self.visible.len().saturating_sub(1);
+        match (key.code, control) {
+            (KeyCode::Enter, _) => return Some(Some(Choice::Line(
+                self.current().map(|line| line.to_owned()).unwrap_or_else(|| self.query.clone())
+            ))),
+            (KeyCode::Esc, _) | (KeyCode::Char('c'), true) => return Some(None),
+            (KeyCode::Up, _) | (KeyCode::Char('k'), true) => self.selected = self.selected.saturating_sub(1),
+            (KeyCode::Down, _) | (KeyCode::Char('j'), true) => self.selected = (self.selected + 1).min(last),
+            (KeyCode::PageUp, _) => self.selected = self.selected.saturating_sub(10),
+            (KeyCode::PageDown, _) => self.selected = (self.selected + 10).min(last),
+            (KeyCode::Home, _) => self.selected = 0,
+            (KeyCode::End, _) => self.selected = last,
+            (KeyCode::Backspace, _) => {
+                self.query.pop();
+                self.filter();
+            },
+            (KeyCode::Char(c), false) => {
+                self.query.push(c);
+                self.filter();
+            },
+            _ => {},
+        }
+        None
+    }
+
+    fn draw<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let rows = height.saturating_sub(2) as usize;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if rows > 0 && self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+        let preview = self.visible.get(self.selected)
+            .map(|&i| &self.menu.entries[i].preview)
+            .filter(|p| !p.is_empty() && width >= PREVIEW_MIN_WIDTH);
+        let list_width = if preview.is_some() { width / 2 } else { width } as usize;
+
+        queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+        let prompt = format!("{} {}", self.menu.prompt.unwrap_or(">"), self.query);
+        queue!(out, Print(truncate(&prompt, width as usize)))?;
+
+        for (row, &index) in self.visible.iter().enumerate().skip(self.offset).take(rows) {
+            let line = truncate(&self.menu.entries[index].line, list_width.saturating_sub(1));
+            queue!(out, MoveTo(0, (row - self.offset + 1) as u16))?;
+            if row == self.selected {
+                queue!(out, SetAttribute(Attribute::Reverse), Print(line), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(out, Print(line))?;
+            }
+        }
+
+        if let Some(preview) = preview {
+            let preview_width = width as usize - list_width - 2;
+            for row in 0..rows {
+                queue!(out, MoveTo(list_width as u16, (row + 1) as u16), Print("│ "))?;
+                if let Some(line) = preview.get(row) {
+                    queue!(out, Print(truncate(line, preview_width)))?;
+                }
+            }
+        }
+
+        let status = if self.menu.shortcuts {
+            format!("{}/{}  {}", self.visible.len(), self.menu.entries.len(), HELP)
+        } else {
+            format!("{}/{}", self.visible.len(), self.menu.entries.len())
+        };
+        queue!(out, MoveTo(0, height.saturating_sub(1)), SetAttribute(Attribute::Dim),
+            Print(truncate(&status, width as usize)), SetAttribute(Attribute::Reset))?;
+        out.flush()
+    }
+}
+
+/// Every word of the query appears in the line, scored by how well
+fn line_score(query: &str, line: &str) -> Option<i64> {
+    query.split_whitespace().map(|word| fuzzy::score(word, line)).sum()
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
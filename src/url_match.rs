@@ -0,0 +1,249 @@
+use std::net::IpAddr;
+use url::Url;
+use crate::model::logical;
+
+/// How well an item matches what the user is looking at, best last
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Score {
+    None,
+    /// Item name appears in the window title
+    Name,
+    /// Same registrable domain, e.g. `gist.github.com` for `github.com`
+    Domain,
+    /// Exactly the same host
+    Host,
+}
+
+#[derive(Debug)]
+pub struct Ranked<'a> {
+    /// Matching items first, best match at the top, followed by the rest
+    pub items: Vec<&'a logical::Item>,
+    /// Number of items at the front of the list that matched
+    pub matched: usize,
+    /// How well they matched
+    pub best: Score,
+}
+
+// URL with a host, assuming https when there is no scheme
+fn parse(url: &str) -> Option<Url> {
+    let url = url.trim();
+    Url::parse(url).ok()
+        .filter(|u| u.has_host())
+        .or_else(|| Url::parse(&format!("https://{}", url)).ok())
+}
+
+/// Host part of a URL with its port when one other than the scheme's default
+/// is given, assuming https when there is no scheme
+pub fn host(url: &str) -> Option<String> {
+    let url = parse(url)?;
+    let host = hostname_of(&url)?;
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    })
+}
+
+/// Host part of a URL without any port, assuming https when there is no scheme
+pub fn hostname(url: &str) -> Option<String> {
+    hostname_of(&parse(url)?)
+}
+
+fn hostname_of(url: &Url) -> Option<String> {
+    url.host_str()
+        .map(|h| h.trim_end_matches('.').to_lowercase())
+        .filter(|h| !h.is_empty())
+}
+
+/// Scheme of a URL, https when there is none
+pub fn scheme(url: &str) -> Option<String> {
+    parse(url).map(|u| u.scheme().to_owned())
+}
+
+/// Number of path segments a URL has when they all lead the given path, so
+/// `org/repo` matches `org/repo.git` but not `org/repo-evil`. A URL without a
+/// path matches any path with 0.
+pub fn path_depth(url: &str, path: &str) -> Option<usize> {
+    let url_path = parse(url)?.path().to_owned();
+    let url_segments = url_path.split('/').filter(|s| !s.is_empty()).map(strip_git).collect::<Vec<_>>();
+    let mut segments = path.split('/').filter(|s| !s.is_empty()).map(strip_git);
+    if url_segments.iter().all(|s| segments.next() == Some(s)) {
+        Some(url_segments.len())
+    } else {
+        None
+    }
+}
+
+fn strip_git(segment: &str) -> &str {
+    segment.strip_suffix(".git").unwrap_or(segment)
+}
+
+/// Registrable domain of a host according to the public suffix list, whatever its port
+fn domain(host: &str) -> Option<&str> {
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    if host.trim_matches(|c| c == '[' || c == ']').parse::<IpAddr>().is_ok() {
+        return None
+    }
+    psl::domain_str(host)
+}
+
+/// Hosts mentioned in free text such as a window title, either as full URLs
+/// or as bare domain names with a known public suffix
+pub fn hosts_in_text(text: &str) -> Vec<String> {
+    let mut hosts: Vec<String> = vec![];
+    let words = text.split(|c: char| c.is_whitespace() || "|()<>[]\"',·—–".contains(c));
+    for word in words {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        let found = if word.contains("://") {
+            host(word)
+        } else if word.contains('.') {
+            let bare = word.split('/').next().unwrap_or(word).to_lowercase();
+            psl::suffix(bare.as_bytes())
+                .filter(|suffix| suffix.is_known() && suffix.as_bytes() != bare.as_bytes())
+                .map(|_| bare.clone())
+        } else {
+            None
+        };
+        if let Some(found) = found {
+            if !hosts.contains(&found) {
+                hosts.push(found);
+            }
+        }
+    }
+    hosts
+}
+
+/// Best score of an item's URL against any of the hosts
+pub fn url_score(hosts: &[String], item: &logical::Item) -> Score {
+    let item_host = match item.url.as_deref().and_then(host) {
+        Some(h) => h,
+        None => return Score::None,
+    };
+    hosts.iter().map(|h| {
+        if *h == item_host {
+            Score::Host
+        } else if domain(h).is_some() && domain(h) == domain(&item_host) {
+            Score::Domain
+        } else {
+            Score::None
+        }
+    }).max().unwrap_or(Score::None)
+}
+
+/// Score items by their name appearing in a window title
+pub fn title_score(title: &str, item: &logical::Item) -> Score {
+    let title = title.to_lowercase();
+    let name = item.name.trim().to_lowercase();
+    if !name.is_empty() && title.contains(&name) {
+        Score::Name
+    } else {
+        Score::None
+    }
+}
+
+/// Stable sort of items by score, keeping the original order within a score
+pub fn rank<F: Fn(&logical::Item) -> Score>(items: Vec<&logical::Item>, score: F) -> Ranked<'_> {
+    let mut scored = items.into_iter()
+        .map(|item| (score(item), item))
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+    let best = scored.first().map(|(s, _)| *s).unwrap_or(Score::None);
+    // Only the strongest kind of match counts, a host match beats a pile of name matches
+    let matched = if best == Score::None {
+        0
+    } else {
+        scored.iter().take_while(|(s, _)| *s == best).count()
+    };
+    Ranked {
+        items: scored.into_iter().map(|(_, item)| item).collect(),
+        matched,
+        best,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::logical::Item;
+
+    fn names(ranked: &Ranked) -> Vec<String> {
+        ranked.items.iter().map(|i| i.name.clone()).collect()
+    }
+
+    #[test]
+    fn hosts_in_text_finds_urls_and_bare_domains() {
+        let hosts = hosts_in_text("Pull requests · GitHub — https://github.com/org/repo | gist.github.com (Firefox)");
+        assert_eq!(hosts, vec!["github.com", "gist.github.com"]);
+    }
+
+    #[test]
+    fn hosts_in_text_ignores_unknown_suffixes_and_file_names() {
+        assert!(hosts_in_text("notes.txt - Text Editor").is_empty());
+        assert!(hosts_in_text("Release 1.2.3 and a plain sentence.").is_empty());
+        assert_eq!(hosts_in_text("Sign in - accounts.google.com"), vec!["accounts.google.com"]);
+    }
+
+    #[test]
+    fn rank_puts_host_matches_before_domain_matches() {
+        let items = [
+            Item::named("Other", Some("https://example.org")),
+            Item::named("Gist", Some("https://gist.github.com")),
+            Item::named("GitHub", Some("https://github.com/login")),
+        ];
+        let hosts = vec!["github.com".to_owned()];
+        let ranked = rank(items.iter().collect(), |i| url_score(&hosts, i));
+        assert_eq!(names(&ranked), vec!["GitHub", "Gist", "Other"]);
+        assert_eq!(ranked.matched, 1);
+        assert_eq!(ranked.best, Score::Host);
+    }
+
+    #[test]
+    fn rank_counts_only_the_best_kind_of_match() {
+        let items = [
+            Item::named("Gist", Some("gist.github.com")),
+            Item::named("Pages", Some("https://pages.github.com")),
+            Item::named("Other", None),
+        ];
+        let hosts = vec!["github.com".to_owned()];
+        let ranked = rank(items.iter().collect(), |i| url_score(&hosts, i));
+        assert_eq!(names(&ranked), vec!["Gist", "Pages", "Other"]);
+        assert_eq!(ranked.matched, 2);
+        assert_eq!(ranked.best, Score::Domain);
+    }
+
+    #[test]
+    fn rank_reports_name_matches_and_no_matches() {
+        let items = [Item::named("Jira", None), Item::named("GitHub", None)];
+        let ranked = rank(items.iter().collect(), |i| title_score("Issues · GitHub - Firefox", i));
+        assert_eq!(names(&ranked), vec!["GitHub", "Jira"]);
+        assert_eq!((ranked.matched, ranked.best), (1, Score::Name));
+
+        let ranked = rank(items.iter().collect(), |i| title_score("Terminal", i));
+        assert_eq!(names(&ranked), vec!["Jira", "GitHub"]);
+        assert_eq!((ranked.matched, ranked.best), (0, Score::None));
+    }
+
+    #[test]
+    fn host_keeps_ports_other_than_the_default() {
+        assert_eq!(host("https://git.example.com:8443/org/repo").as_deref(), Some("git.example.com:8443"));
+        assert_eq!(host("https://git.example.com:443/org/repo").as_deref(), Some("git.example.com"));
+        assert_eq!(host("localhost:5000").as_deref(), Some("localhost:5000"));
+        assert_eq!(host("http://[::1]:8080").as_deref(), Some("[::1]:8080"));
+        assert_eq!(hostname("https://git.example.com:8443").as_deref(), Some("git.example.com"));
+    }
+
+    #[test]
+    fn other_ports_are_not_the_same_host() {
+        let items = [
+            Item::named("Default", Some("https://example.com")),
+            Item::named("Admin", Some("https://example.com:8443/admin")),
+        ];
+        let hosts = vec!["example.com:8443".to_owned()];
+        let ranked = rank(items.iter().collect(), |i| url_score(&hosts, i));
+        assert_eq!(names(&ranked), vec!["Admin", "Default"]);
+        assert_eq!((ranked.matched, ranked.best), (1, Score::Host));
+        assert_eq!(url_score(&hosts, &items[0]), Score::Domain);
+    }
+}
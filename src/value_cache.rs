@@ -0,0 +1,208 @@
+use anyhow::{Context, Result, Error};
+use std::path::{Path, PathBuf};
+use std::io::ErrorKind;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, Payload};
+use linux_keyutils::{KeyRing, KeyRingIdentifier};
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::model::config::ValueCache;
+use crate::model::logical::{FullField, Item};
+use crate::model::storage::{Sealed, SealedValues};
+use crate::filter;
+use crate::cache;
+
+const VALUES_PATH: &str = "~/.config/r1pw/values.json";
+/// Kernel keyring key holding the encryption key, so it never touches the disk
+const KEY_PREFIX: &str = "r1pw:value-cache";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+static CONFIG: OnceLock<ValueCache> = OnceLock::new();
+
+/// A field as encrypted
+#[derive(Serialize, Deserialize)]
+struct SealedField {
+    name: String,
+    designation: String,
+    value: String,
+    #[serde(default)]
+    section: Option<String>,
+}
+
+/// Use these rules for the rest of the run
+pub fn init(config: ValueCache) {
+    let _ = CONFIG.set(config);
+}
+
+fn config() -> Option<&'static ValueCache> {
+    CONFIG.get()
+}
+
+/// Whether the rules keep this item's values
+pub fn applies(config: &ValueCache, item: &Item) -> bool {
+    let excluded = config.exclude_vaults.iter().any(|v| filter::in_vault(v, item));
+    let listed = config.items.iter().any(|i| *i == item.name || *i == item.uuid)
+        || item.tags.iter().any(|t| config.tags.contains(t));
+    listed && !excluded
+}
+
+/// Cached fields of an item, if it is kept and its values haven't expired
+pub fn get(item: &Item) -> Option<Vec<FullField>> {
+    let config = config().filter(|c| applies(c, item))?;
+    match read(&item.uuid, config) {
+        Ok(fields) => fields,
+        Err(e) => {
+            warn!("Unable to read cached values of {}: {:#}", item.name, e);
+            None
+        },
+    }
+}
+
+/// Keep an item's fields, if the rules say so
+pub fn put(item: &Item, fields: &[FullField]) {
+    if let Some(config) = config().filter(|c| applies(c, item)) {
+        if let Err(e) = write(&item.uuid, fields, config) {
+            warn!("Unable to cache values of {}: {:#}", item.name, e);
+        }
+    }
+}
+
+/// Forget every cached value, and the key that could decrypt them
+pub fn clear() -> Result<()> {
+    // Without a keyring there is no key to forget, and nothing could have been cached
+    match keyring() {
+        Ok(keyring) => if let Ok(key) = keyring.search(&key_description()?) {
+            key.invalidate().map_err(|e| Error::msg(format!("Error invalidating value cache key: {}", e)))?;
+        },
+        Err(e) => debug!("No value cache key to forget: {:#}", e),
+    }
+    match std::fs::remove_file(get_values_path()?) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn read(uuid: &str, config: &ValueCache) -> Result<Option<Vec<FullField>>> {
+    let sealed = match read_values()?.items.remove(uuid) {
+        Some(sealed) if sealed.expires > now() => sealed,
+        _ => return Ok(None),
+    };
+    // An expired key takes every value with it
+    let key = match keyring()?.search(&key_description()?) {
+        Ok(key) => key.read_to_vec().map_err(|e| Error::msg(format!("Error reading value cache key: {}", e)))?,
+        Err(_) => return Ok(None),
+    };
+    let nonce = decode(&sealed.nonce)?;
+    let ciphertext = decode(&sealed.ciphertext)?;
+    if key.len() != KEY_LENGTH || nonce.len() != NONCE_LENGTH {
+        return Ok(None)
+    }
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    // The UUID is authenticated too, so values can't be moved onto another item
+    let plaintext = match cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: uuid.as_bytes() }) {
+        Ok(plaintext) => plaintext,
+        Err(_) => {
+            debug!("Cached values of {} were sealed with another key", uuid);
+            return Ok(None)
+        },
+    };
+    let fields = serde_json::from_slice::<Vec<SealedField>>(&plaintext)
+        .with_context(||"Error de-serialising cached values")?
+        .into_iter()
+        .map(|f| FullField { name: f.name, designation: f.designation, value: f.value, section: f.section })
+        .collect();
+    debug!("Using cached values of {}, ttl {}s", uuid, config.ttl);
+    Ok(Some(fields))
+}
+
+fn write(uuid: &str, fields: &[FullField], config: &ValueCache) -> Result<()> {
+    let keyring = keyring()?;
+    let key = match keyring.search(&key_description()?).ok().and_then(|k| k.read_to_vec().ok().map(|v| (k, v))) {
+        Some((key, value)) if value.len() == KEY_LENGTH => {
+            key.set_timeout(config.ttl as usize)
+                .map_err(|e| Error::msg(format!("Error extending value cache key: {}", e)))?;
+            value
+        },
+        _ => {
+            let mut value = vec![0u8; KEY_LENGTH];
+            getrandom::getrandom(&mut value).map_err(|e| Error::msg(format!("Error generating key: {}", e)))?;
+            let key = keyring.add_key(&key_description()?, &value)
+                .map_err(|e| Error::msg(format!("Error adding value cache key to the keyring: {}", e)))?;
+            key.set_timeout(config.ttl as usize)
+                .map_err(|e| Error::msg(format!("Error setting value cache key timeout: {}", e)))?;
+            value
+        },
+    };
+
+    let mut nonce = vec![0u8; NONCE_LENGTH];
+    getrandom::getrandom(&mut nonce).map_err(|e| Error::msg(format!("Error generating nonce: {}", e)))?;
+    let plaintext = serde_json::to_vec(&fields.iter()
+        .map(|f| SealedField {
+            name: f.name.clone(),
+            designation: f.designation.clone(),
+            value: f.value.clone(),
+            section: f.section.clone(),
+        })
+        .collect::<Vec<_>>())?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: uuid.as_bytes() })
+        .map_err(|_| Error::msg("Error encrypting values"))?;
+
+    let mut values = read_values()?;
+    let now = now();
+    values.items.retain(|_, sealed| sealed.expires > now);
+    values.items.insert(uuid.to_owned(), Sealed {
+        expires: now + config.ttl,
+        nonce: encode(&nonce),
+        ciphertext: encode(&ciphertext),
+    });
+    cache::write_private(&get_values_path()?, &serde_json::to_string(&values)?)
+}
+
+// One key per cache file, so each home has its own
+fn key_description() -> Result<String> {
+    Ok(format!("{}:{}", KEY_PREFIX, get_values_path()?.display()))
+}
+
+fn keyring() -> Result<KeyRing> {
+    KeyRing::from_special_id(KeyRingIdentifier::User, true)
+        .map_err(|e| Error::msg(format!("Error opening the user keyring: {}", e)))
+}
+
+// Missing values are the same as none
+fn read_values() -> Result<SealedValues> {
+    let path = get_values_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(c) => serde_json::from_str(&c)
+            .with_context(|| format!("Error de-serialising value cache {:?}", path)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(SealedValues::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode(hex: &str) -> Result<Vec<u8>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err(Error::msg("Malformed hex in value cache"))
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).with_context(||"Bad hex in value cache"))
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn get_values_path() -> Result<PathBuf> {
+    let path = shellexpand::full(VALUES_PATH)
+        .with_context(|| format!("Value cache path {} is invalid", VALUES_PATH))?;
+    Ok(Path::new(path.as_ref()).to_owned())
+}
@@ -0,0 +1,119 @@
+use std::convert::TryFrom;
+use std::os::unix::fs::MetadataExt;
+use std::sync::mpsc;
+use std::thread;
+use anyhow::{Result, Error};
+use log::{debug, info, warn};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::message::Type;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::MatchRule;
+
+use crate::session;
+
+/// D-Bus signal meaning the user has stepped away
+struct Signal {
+    system_bus: bool,
+    interface: &'static str,
+    member: &'static str,
+    /// Only when its boolean argument is true, it is sent with false on the way back too
+    when_true: bool,
+    /// Only for the user's own session, logind sends it to each session's object
+    own_session: bool,
+}
+
+const SIGNALS: &[Signal] = &[
+    Signal { system_bus: true, interface: "org.freedesktop.login1.Manager", member: "PrepareForSleep", when_true: true, own_session: false },
+    Signal { system_bus: true, interface: "org.freedesktop.login1.Session", member: "Lock", when_true: false, own_session: true },
+    Signal { system_bus: false, interface: "org.freedesktop.ScreenSaver", member: "ActiveChanged", when_true: true, own_session: false },
+    Signal { system_bus: false, interface: "org.gnome.ScreenSaver", member: "ActiveChanged", when_true: true, own_session: false },
+];
+
+/// Lock whenever logind says the machine is about to sleep or the user's session should lock,
+/// or a screensaver starts. Runs until neither bus can be reached.
+pub fn watch() -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watching = 0;
+    for system_bus in [true, false] {
+        let bus = if system_bus { "system" } else { "session" };
+        let connection = match if system_bus { Connection::system() } else { Connection::session() } {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Not watching the {} bus: {}", bus, e);
+                continue
+            },
+        };
+        for signal in SIGNALS.iter().filter(|s| s.system_bus == system_bus) {
+            let mut rule = MatchRule::builder()
+                .msg_type(Type::Signal)
+                .interface(signal.interface)?
+                .member(signal.member)?;
+            if signal.own_session {
+                match own_session(&connection) {
+                    Ok(session) => rule = rule.path(session)?,
+                    Err(e) => {
+                        warn!("Not watching {}.{}, unable to find the user's session: {:#}", signal.interface, signal.member, e);
+                        continue
+                    },
+                }
+            }
+            let rule = rule.build();
+            let messages = MessageIterator::for_match_rule(rule, &connection, None)?;
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for message in messages {
+                    let message = match message {
+                        Ok(message) => message,
+                        Err(e) => {
+                            warn!("Error receiving {}: {}", signal.member, e);
+                            continue
+                        },
+                    };
+                    if signal.when_true && !message.body().deserialize::<bool>().unwrap_or(false) {
+                        continue
+                    }
+                    if sender.send(format!("{}.{}", signal.interface, signal.member)).is_err() {
+                        break
+                    }
+                }
+            });
+            debug!("Watching {}.{} on the {} bus", signal.interface, signal.member, bus);
+            watching += 1;
+        }
+    }
+    if watching == 0 {
+        return Err(Error::msg("Unable to reach either D-Bus bus, nothing to watch"))
+    }
+    drop(sender);
+    for event in receiver {
+        info!("Locking on {}", event);
+        if let Err(e) = session::lock() {
+            warn!("Unable to lock: {:#}", e);
+        }
+    }
+    Err(Error::msg("Lost the connection to D-Bus"))
+}
+
+fn login1(connection: &Connection, path: &str, interface: &str, method: &str, body: &(impl serde::Serialize + zbus::zvariant::DynamicType)) -> Result<zbus::message::Message> {
+    Ok(connection.call_method(Some("org.freedesktop.login1"), path, Some(interface), method, body)?)
+}
+
+/// logind's object for the session r1pw runs in, or failing that, the user's graphical one
+fn own_session(connection: &Connection) -> Result<OwnedObjectPath> {
+    let manager = "org.freedesktop.login1.Manager";
+    match login1(connection, "/org/freedesktop/login1", manager, "GetSessionByPID", &(std::process::id())) {
+        Ok(reply) => return Ok(reply.body().deserialize()?),
+        Err(e) => debug!("Not in a session: {:#}", e),
+    }
+    // Services of the user's systemd run outside of any session
+    let uid = std::fs::metadata("/proc/self")?.uid();
+    let user: OwnedObjectPath = login1(connection, "/org/freedesktop/login1", manager, "GetUser", &(uid))?
+        .body().deserialize()?;
+    let display: OwnedValue = login1(connection, user.as_str(), "org.freedesktop.DBus.Properties", "Get", &("org.freedesktop.login1.User", "Display"))?
+        .body().deserialize()?;
+    let (_, session) = <(String, OwnedObjectPath)>::try_from(display)?;
+    if session.as_str() == "/" {
+        return Err(Error::msg("The user has no graphical session"))
+    }
+    Ok(session)
+}
@@ -0,0 +1,52 @@
+use std::env;
+use anyhow::{Result, Error, Context};
+use serde_json::Value;
+
+use crate::commands;
+
+/// Title of the currently focused window.
+///
+/// Uses xdotool on X11, and the compositor's own IPC on Sway and Hyprland
+/// since Wayland has no common way of asking for it.
+pub fn active_title() -> Result<String> {
+    if env::var_os("SWAYSOCK").is_some() {
+        let tree = run("swaymsg", &["-t", "get_tree"])?;
+        let tree: Value = serde_json::from_str(&tree)
+            .with_context(||"Failed to de-serialise sway tree")?;
+        find_focused(&tree).ok_or_else(||Error::msg("No focused window found in sway tree"))
+    } else if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let window = run("hyprctl", &["activewindow", "-j"])?;
+        let window: Value = serde_json::from_str(&window)
+            .with_context(||"Failed to de-serialise hyprctl output")?;
+        window["title"].as_str()
+            .map(|t| t.to_owned())
+            .ok_or_else(||Error::msg("No active window reported by hyprctl"))
+    } else if env::var_os("DISPLAY").is_some() {
+        run("xdotool", &["getactivewindow", "getwindowname"])
+    } else {
+        Err(Error::msg("Unable to read window title, no supported display found"))
+    }
+}
+
+fn find_focused(node: &Value) -> Option<String> {
+    if node["focused"].as_bool() == Some(true) {
+        return node["name"].as_str().map(|n| n.to_owned())
+    }
+    ["nodes", "floating_nodes"].iter()
+        .filter_map(|key| node[*key].as_array())
+        .flatten()
+        .find_map(find_focused)
+}
+
+fn run(command: &str, args: &[&str]) -> Result<String> {
+    let output = commands::command(command)?
+        .args(args)
+        .output()
+        .with_context(||format!("Failed to run {}", command))?;
+    if !output.status.success() {
+        return Err(Error::msg(format!(
+            "{} failed: {}", command, String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{Sandbox, stderr, stdout, PASSWORD, TOKEN};
+
+#[test]
+fn missing_op_is_reported_by_name() {
+    let mut sandbox = Sandbox::new("missing_op");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .answers(&[PASSWORD]);
+
+    // Only what the fakes need, without op
+    let tools = sandbox.dir.join("tools");
+    std::fs::create_dir(&tools).unwrap();
+    for tool in &["cat", "head", "tail", "mv", "sed", "dirname"] {
+        let path = std::env::split_paths(&std::env::var_os("PATH").unwrap())
+            .map(|dir| dir.join(tool))
+            .find(|path| path.is_file())
+            .unwrap();
+        std::os::unix::fs::symlink(path, tools.join(tool)).unwrap();
+    }
+
+    let output = sandbox.command(&[])
+        .env_remove("R1PW_OP")
+        .env("PATH", &tools)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("op not found on PATH"), "{}", stderr(&output));
+}
+
+#[test]
+fn missing_configured_path_is_reported() {
+    let mut sandbox = Sandbox::new("missing_path");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN);
+
+    let output = sandbox.command(&[]).env("R1PW_DMENU", "/nonexistent/dmenu").output().unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("dmenu not found at /nonexistent/dmenu"), "{}", stderr(&output));
+}
+
+#[test]
+fn configured_paths_and_arguments_are_used() {
+    let mut sandbox = Sandbox::new("configured_commands");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["@1", "@2"]);
+    let dmenu = sandbox.dir.join("dmenu");
+    sandbox.write_config(&format!(
+        r#"{{"commands": {{"dmenu": {{"path": "{}", "args": ["-fn", "monospace"]}}}}}}"#,
+        dmenu.display()
+    ));
+
+    let output = sandbox.command(&[]).env_remove("R1PW_DMENU").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.dmenu_log().starts_with("-fn monospace -b -i -l 20"), "{}", sandbox.dmenu_log());
+    assert_eq!(sandbox.clipboard().as_deref(), Some("s3cret"));
+}
+
+#[test]
+fn environment_arguments_replace_configured_ones() {
+    let mut sandbox = Sandbox::new("environment_arguments");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["@1", "@2"]);
+    sandbox.write_config(r#"{"commands": {"dmenu": {"args": ["-fn", "monospace"]}}}"#);
+
+    let output = sandbox.command(&[]).env("R1PW_DMENU_ARGS", "-i -nb black").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.dmenu_log().starts_with("-i -nb black -b"), "{}", sandbox.dmenu_log());
+}
+
+#[test]
+fn without_a_display_values_are_copied_through_the_terminal() {
+    let sandbox = Sandbox::new("copy_without_display");
+
+    let output = sandbox.run_in_terminal(&["generate", "--preset", "pin6"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+
+    // xsel would need an X server, the terminal is asked to set its clipboard instead
+    assert_eq!(sandbox.clipboard(), None);
+    let terminal = stdout(&output);
+    let start = terminal.find("\x1b]52;c;").expect("OSC 52 sequence written") + 7;
+    let end = start + terminal[start..].find('\x07').unwrap();
+    let pin = decode_base64(&terminal[start..end]);
+    assert!(pin.len() == 6 && pin.chars().all(|c| c.is_ascii_digit()), "{}", pin);
+}
+
+fn decode_base64(encoded: &str) -> String {
+    const ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let bits = encoded.trim_end_matches('=').chars()
+        .map(|c| format!("{:06b}", ALPHABET.find(c).unwrap()))
+        .collect::<String>();
+    let bytes = bits.as_bytes().chunks_exact(8)
+        .map(|byte| u8::from_str_radix(std::str::from_utf8(byte).unwrap(), 2).unwrap())
+        .collect::<Vec<_>>();
+    String::from_utf8(bytes).unwrap()
+}
@@ -0,0 +1,367 @@
+//! Runs the r1pw binary in a sandboxed home, against scripted fakes of op, dmenu and xsel.
+//!
+//! The fakes keep their state in files beside them:
+//! - `password` is the master password `op signin` accepts, `token` the session it hands out
+//!   and every other op command requires
+//! - `items-<shorthand>.json` answers `op list items`, `vaults-<shorthand>.json` `op list vaults`
+//!   and `item-<uuid>.json` `op get item`
+//! - `answers` has one dmenu answer per line: `@N` picks the Nth line shown, `!cancel` cancels,
+//!   `=text` types in text that would otherwise look like one of these, and anything else is
+//!   typed in. Running out of answers cancels too.
+//! - `offline` makes op fail as it does without a network
+//! - `delay` makes op sleep that many seconds before answering
+//! - `op.log` and `dmenu.log` record every call, `clipboard` what xsel was given
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use serde_json::{json, Value};
+
+const OP: &str = r#"#!/bin/sh
+dir="$(dirname "$0")"
+echo "$*" >> "$dir/op.log"
+session=""
+account=""
+prev=""
+for arg in "$@"; do
+    [ "$prev" = "--session" ] && session="$arg"
+    [ "$prev" = "--account" ] && account="$arg"
+    prev="$arg"
+done
+[ -f "$dir/delay" ] && sleep "$(cat "$dir/delay")"
+if [ -f "$dir/offline" ]; then
+    echo "[ERROR] 2026/10/19 09:00:00 Get \"https://my.1password.com/api/v1/account\": dial tcp: lookup my.1password.com: no such host" >&2
+    exit 1
+fi
+if [ "$1" = "signin" ]; then
+    read -r password
+    if [ "$password" = "$(cat "$dir/password")" ]; then
+        cat "$dir/token"
+        exit 0
+    fi
+    echo "[ERROR] Authentication failed" >&2
+    exit 1
+fi
+if [ "$session" != "$(cat "$dir/token")" ]; then
+    echo "[ERROR] You are not currently signed in" >&2
+    exit 1
+fi
+case "$1 $2" in
+"list items") cat "$dir/items-$account.json" ;;
+"list vaults") cat "$dir/vaults-$account.json" ;;
+"get item") cat "$dir/item-$3.json" 2>/dev/null || { echo "[ERROR] Item not found" >&2; exit 1; } ;;
+"signout --account") ;;
+*) echo "[ERROR] Unexpected command $*" >&2; exit 1 ;;
+esac
+"#;
+
+const DMENU: &str = r#"#!/bin/sh
+dir="$(dirname "$0")"
+echo "$*" >> "$dir/dmenu.log"
+input="$(cat)"
+answer="$(head -n 1 "$dir/answers" 2>/dev/null)"
+tail -n +2 "$dir/answers" > "$dir/answers.rest" 2>/dev/null
+mv "$dir/answers.rest" "$dir/answers"
+case "$answer" in
+""|"!cancel") exit 1 ;;
+@*) printf '%s\n' "$input" | sed -n "${answer#@}p" ;;
+=*) printf '%s\n' "${answer#=}" ;;
+*) printf '%s\n' "$answer" ;;
+esac
+"#;
+
+const XSEL: &str = r#"#!/bin/sh
+cat > "$(dirname "$0")/clipboard"
+"#;
+
+pub const PASSWORD: &str = "hunter2";
+/// Version of the items `warm_cache` writes, items listed by older versions are listed again
+pub const ITEMS_VERSION: u32 = 4;
+pub const TOKEN: &str = "session-token";
+
+pub struct Sandbox {
+    pub dir: PathBuf,
+    accounts: Vec<Value>,
+}
+
+impl Sandbox {
+    /// Fresh sandbox named after the test, with one account `my` and op accepting `PASSWORD`
+    pub fn new(name: &str) -> Self {
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("home/.op")).unwrap();
+        fs::create_dir_all(dir.join("home/.config/r1pw")).unwrap();
+        for (name, script) in &[("op", OP), ("dmenu", DMENU), ("xsel", XSEL)] {
+            let path = dir.join(name);
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        fs::write(dir.join("password"), PASSWORD).unwrap();
+        fs::write(dir.join("token"), TOKEN).unwrap();
+        let mut sandbox = Sandbox { dir, accounts: vec![] };
+        sandbox.account("my");
+        sandbox
+    }
+
+    /// Add an account to op's configuration
+    pub fn account(&mut self, shorthand: &str) -> &mut Self {
+        self.accounts.push(json!({
+            "shorthand": shorthand,
+            "url": format!("https://{}.1password.com", shorthand),
+            "email": format!("me@{}.example", shorthand),
+            "userUUID": format!("user-{}", shorthand),
+        }));
+        let config = json!({ "latest_signin": "my", "accounts": self.accounts });
+        fs::write(self.dir.join("home/.op/config"), config.to_string()).unwrap();
+        self
+    }
+
+    /// Items op lists for an account in its `Private` vault, and the fields it returns for each
+    pub fn op_items(&mut self, shorthand: &str, items: &[(&str, &str, &str, &str)]) -> &mut Self {
+        self.op_vault_items(shorthand, ("vault", "Private"), items)
+    }
+
+    /// Items op lists for an account in a vault given as (UUID, name), after those already added
+    pub fn op_vault_items(&mut self, shorthand: &str, vault: (&str, &str), items: &[(&str, &str, &str, &str)]) -> &mut Self {
+        let mut vaults = read_list(&self.dir.join(format!("vaults-{}.json", shorthand)));
+        if !vaults.iter().any(|v| v["uuid"] == vault.0) {
+            vaults.push(json!({ "uuid": vault.0, "name": vault.1 }));
+        }
+        fs::write(self.dir.join(format!("vaults-{}.json", shorthand)), Value::from(vaults).to_string()).unwrap();
+        let mut list = read_list(&self.dir.join(format!("items-{}.json", shorthand)));
+        list.extend(items.iter().map(|(uuid, title, username, _)| json!({
+            "uuid": uuid,
+            "vaultUuid": vault.0,
+            "templateUuid": "001",
+            "overview": { "title": title, "ainfo": username, "url": format!("https://{}.example", title.to_lowercase()) },
+        })));
+        fs::write(self.dir.join(format!("items-{}.json", shorthand)), Value::from(list).to_string()).unwrap();
+        for (uuid, _, username, password) in items {
+            let item = json!({
+                "uuid": uuid,
+                "details": { "fields": [
+                    { "designation": "username", "name": "username", "value": username },
+                    { "designation": "password", "name": "password", "value": password },
+                ] },
+            });
+            fs::write(self.dir.join(format!("item-{}.json", uuid)), item.to_string()).unwrap();
+        }
+        self
+    }
+
+    /// Make an item listed by `op_items` of another kind, with details as op returns them
+    pub fn op_details(&mut self, shorthand: &str, uuid: &str, template_uuid: &str, details: Value) -> &mut Self {
+        let path = self.dir.join(format!("items-{}.json", shorthand));
+        let mut list = read_list(&path);
+        for item in list.iter_mut().filter(|i| i["uuid"] == uuid) {
+            item["templateUuid"] = template_uuid.into();
+        }
+        fs::write(&path, Value::from(list).to_string()).unwrap();
+        let item = json!({ "uuid": uuid, "details": details });
+        fs::write(self.dir.join(format!("item-{}.json", uuid)), item.to_string()).unwrap();
+        self
+    }
+
+    /// Give an item listed by `op_items` another URL than the one made up from its title
+    pub fn op_url(&mut self, shorthand: &str, uuid: &str, url: &str) -> &mut Self {
+        let path = self.dir.join(format!("items-{}.json", shorthand));
+        let mut list = read_list(&path);
+        for item in list.iter_mut().filter(|i| i["uuid"] == uuid) {
+            item["overview"]["url"] = url.into();
+        }
+        fs::write(&path, Value::from(list).to_string()).unwrap();
+        self
+    }
+
+    /// Tag an item listed by `op_items`
+    pub fn op_tags(&mut self, shorthand: &str, uuid: &str, tags: &[&str]) -> &mut Self {
+        let path = self.dir.join(format!("items-{}.json", shorthand));
+        let mut list = read_list(&path);
+        for item in list.iter_mut().filter(|i| i["uuid"] == uuid) {
+            item["overview"]["tags"] = tags.into();
+        }
+        fs::write(&path, Value::from(list).to_string()).unwrap();
+        self
+    }
+
+    /// What a previous run left behind for the items op lists, logged in with `token`
+    pub fn warm_cache(&mut self, token: &str) -> &mut Self {
+        let accounts = self.accounts.iter().map(|account| {
+            let shorthand = account["shorthand"].as_str().unwrap();
+            let listed = read_list(&self.dir.join(format!("items-{}.json", shorthand)));
+            let vaults = read_list(&self.dir.join(format!("vaults-{}.json", shorthand)));
+            let items = listed.iter().map(|item| {
+                let uuid = item["uuid"].as_str().unwrap();
+                let vault = vaults.iter().find(|v| v["uuid"] == item["vaultUuid"]);
+                let fields: Value = serde_json::from_str(&fs::read_to_string(self.dir.join(format!("item-{}.json", uuid))).unwrap()).unwrap();
+                json!({
+                    "uuid": uuid,
+                    "name": item["overview"]["title"],
+                    "username": if item["templateUuid"] == "001" { item["overview"]["ainfo"].clone() } else { Value::Null },
+                    "vault_uuid": item["vaultUuid"],
+                    "vault_name": vault.map(|v| v["name"].clone()),
+                    "url": item["overview"]["url"],
+                    "tags": item["overview"]["tags"].as_array().cloned().unwrap_or_default(),
+                    // Items of other kinds than logins are fetched again, as their fields are left out
+                    "fields": fields["details"]["fields"].as_array().into_iter().flatten().map(|f| json!({
+                        "name": f["name"],
+                        "designation": f["designation"],
+                        "value_length": f["value"].as_str().unwrap().len(),
+                    })).collect::<Vec<_>>(),
+                })
+            }).collect::<Vec<_>>();
+            json!({
+                "token": token,
+                "shorthand": shorthand,
+                "email": account["email"],
+                "uuid": account["userUUID"],
+                "items": items,
+                "items_version": ITEMS_VERSION,
+            })
+        }).collect::<Vec<_>>();
+        self.write_cache(&json!({ "accounts": accounts }).to_string());
+        self
+    }
+
+    pub fn write_cache(&self, contents: &str) {
+        fs::write(self.cache_path(), contents).unwrap();
+    }
+
+    /// Answers dmenu gives, in order
+    pub fn answers(&mut self, answers: &[&str]) -> &mut Self {
+        fs::write(self.dir.join("answers"), answers.join("\n") + "\n").unwrap();
+        self
+    }
+
+    pub fn write_config(&self, contents: &str) {
+        fs::write(self.dir.join("home/.config/r1pw/config.json"), contents).unwrap();
+    }
+
+    /// r1pw set up to run in the sandbox, for tests to adjust
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_r1pw"));
+        command.args(args)
+            .env("HOME", self.dir.join("home"))
+            .env("R1PW_OP", self.dir.join("op"))
+            .env("R1PW_DMENU", self.dir.join("dmenu"))
+            .env("R1PW_XSEL", self.dir.join("xsel"))
+            .env("DISPLAY", ":0")
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("RUST_LOG");
+        command
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Run with the given standard input
+    pub fn run_with_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self.command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Run on a terminal of its own without a display, returning what was written to the terminal
+    pub fn run_in_terminal(&self, args: &[&str]) -> Output {
+        let mut inner = self.command(args);
+        inner.env_remove("DISPLAY");
+        let line = std::iter::once(inner.get_program())
+            .chain(inner.get_args())
+            .map(|arg| format!("'{}'", arg.to_str().unwrap()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut script = Command::new("script");
+        script.args(["-qec", &line, "/dev/null"]).env_clear().stdin(Stdio::null());
+        for (key, value) in inner.get_envs() {
+            if let Some(value) = value {
+                script.env(key, value);
+            }
+        }
+        script.env("PATH", std::env::var_os("PATH").unwrap_or_default());
+        script.output().unwrap()
+    }
+
+    pub fn cache_path(&self) -> PathBuf {
+        self.dir.join("home/.config/r1pw/cache.json")
+    }
+
+    pub fn cache_contents(&self) -> Option<String> {
+        fs::read_to_string(self.cache_path()).ok()
+    }
+
+    pub fn cache(&self) -> Value {
+        serde_json::from_str(&self.cache_contents().expect("cache.json was written")).unwrap()
+    }
+
+    pub fn clipboard(&self) -> Option<String> {
+        fs::read_to_string(self.dir.join("clipboard")).ok()
+    }
+
+    pub fn op_log(&self) -> String {
+        fs::read_to_string(self.dir.join("op.log")).unwrap_or_default()
+    }
+
+    pub fn dmenu_log(&self) -> String {
+        fs::read_to_string(self.dir.join("dmenu.log")).unwrap_or_default()
+    }
+}
+
+/// Private dbus-daemon, standing in for the system or session bus
+pub struct Bus {
+    daemon: Child,
+    pub address: String,
+}
+
+impl Bus {
+    pub fn start() -> Option<Bus> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+        Some(Bus { daemon, address: address.trim().to_owned() })
+    }
+
+    pub fn signal(&self, path: &str, member: &str, args: &[&str]) {
+        let status = Command::new("dbus-send")
+            .arg(format!("--bus={}", self.address))
+            .args(["--type=signal", path, member])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+fn read_list(path: &Path) -> Vec<Value> {
+    fs::read_to_string(path)
+        .map(|c| serde_json::from_str(&c).unwrap())
+        .unwrap_or_default()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use serde_json::json;
+use common::{Sandbox, stderr, stdout, PASSWORD, TOKEN};
+
+fn git_sandbox(name: &str) -> Sandbox {
+    let mut sandbox = Sandbox::new(name);
+    sandbox.op_items("my", &[
+        ("i1", "Repo", "alice", "repo-secret"),
+        ("i2", "Gist", "alice", "gist-secret"),
+        ("i3", "Plain", "alice", "plain-secret"),
+    ])
+        .op_url("my", "i1", "https://git.example/org/repo")
+        .op_url("my", "i2", "https://gist.git.example")
+        .op_url("my", "i3", "http://plain.example")
+        .warm_cache(TOKEN);
+    sandbox
+}
+
+fn git_get(sandbox: &Sandbox, request: &str) -> String {
+    let output = sandbox.run_with_input(&["git-credential", "get"], request);
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output)
+}
+
+#[test]
+fn git_credential_answers_for_the_exact_host() {
+    let sandbox = git_sandbox("git_exact_host");
+
+    let answer = git_get(&sandbox, "protocol=https\nhost=gist.git.example\n\n");
+    assert_eq!(answer, "username=alice\npassword=gist-secret\n");
+}
+
+#[test]
+fn git_credential_ignores_other_hosts_of_the_same_domain() {
+    let sandbox = git_sandbox("git_same_domain");
+
+    assert_eq!(git_get(&sandbox, "protocol=https\nhost=evil.git.example\n\n"), "");
+    assert!(!sandbox.op_log().contains("get item"), "{}", sandbox.op_log());
+}
+
+#[test]
+fn git_credential_requires_the_same_scheme() {
+    let sandbox = git_sandbox("git_same_scheme");
+
+    assert_eq!(git_get(&sandbox, "protocol=https\nhost=plain.example\n\n"), "");
+    assert_eq!(git_get(&sandbox, "protocol=http\nhost=gist.git.example\n\n"), "");
+    assert_eq!(git_get(&sandbox, "protocol=http\nhost=plain.example\n\n"), "username=alice\npassword=plain-secret\n");
+}
+
+#[test]
+fn git_credential_matches_paths_by_whole_segments() {
+    let sandbox = git_sandbox("git_path_segments");
+
+    let answer = git_get(&sandbox, "protocol=https\nhost=git.example\npath=org/repo.git\n\n");
+    assert_eq!(answer, "username=alice\npassword=repo-secret\n");
+    assert_eq!(git_get(&sandbox, "protocol=https\nhost=git.example\npath=org/repo-evil.git\n\n"), "");
+    assert_eq!(git_get(&sandbox, "protocol=https\nhost=git.example\npath=org\n\n"), "");
+}
+
+#[test]
+fn git_credential_stays_silent_when_the_username_differs() {
+    let sandbox = git_sandbox("git_other_username");
+
+    assert_eq!(git_get(&sandbox, "protocol=https\nhost=gist.git.example\nusername=mallory\n\n"), "");
+    assert!(sandbox.dmenu_log().is_empty(), "{}", sandbox.dmenu_log());
+}
+
+#[test]
+fn git_credential_only_releases_the_designated_password() {
+    let mut sandbox = Sandbox::new("git_designated_password");
+    sandbox.op_items("my", &[("i1", "Server", "alice", "")])
+        .op_url("my", "i1", "https://server.example")
+        .op_details("my", "i1", "001", json!({
+            "fields": [{ "designation": "username", "name": "username", "value": "alice" }],
+            "sections": [{ "name": "", "title": "", "fields": [
+                { "k": "string", "n": "password", "t": "password", "v": "recovery-code" },
+            ] }],
+        }))
+        .warm_cache(TOKEN);
+
+    let output = sandbox.run_with_input(&["git-credential", "get"], "protocol=https\nhost=server.example\n\n");
+    assert!(!output.status.success());
+    assert!(!stdout(&output).contains("recovery-code"), "{}", stdout(&output));
+
+    // Asked for by name, the field is found
+    let output = sandbox.run(&["get", "Server", "--field", "Password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "recovery-code\n");
+}
+
+fn docker_sandbox(name: &str) -> Sandbox {
+    let mut sandbox = Sandbox::new(name);
+    sandbox.op_items("my", &[
+        ("i1", "Registry", "robot", "registry-secret"),
+        ("i2", "Hub", "dockeruser", "hub-secret"),
+    ])
+        .op_url("my", "i1", "https://registry.example")
+        .op_url("my", "i2", "https://docker.io")
+        .warm_cache(TOKEN);
+    sandbox
+}
+
+#[test]
+fn docker_credential_answers_for_the_exact_host() {
+    let sandbox = docker_sandbox("docker_exact_host");
+
+    let output = sandbox.run_with_input(&["docker-credential", "get"], "registry.example\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "{\"ServerURL\":\"registry.example\",\"Username\":\"robot\",\"Secret\":\"registry-secret\"}\n");
+
+    let output = sandbox.run_with_input(&["docker-credential", "get"], "evil.registry.example\n");
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "credentials not found in native keychain\n");
+    assert_eq!(sandbox.op_log().matches("get item").count(), 1, "{}", sandbox.op_log());
+}
+
+#[test]
+fn docker_credential_list_keeps_the_login() {
+    let mut sandbox = Sandbox::new("docker_list_keeps_login");
+    sandbox.op_items("my", &[("i1", "Registry", "robot", "registry-secret")])
+        .op_url("my", "i1", "https://registry.example")
+        .op_tags("my", "i1", &["docker"])
+        .answers(&[PASSWORD]);
+
+    let output = sandbox.run(&["docker-credential", "list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "{\"https://registry.example\":\"robot\"}\n");
+    assert_eq!(sandbox.cache()["accounts"][0]["token"], TOKEN);
+    assert_eq!(sandbox.cache()["accounts"][0]["items"][0]["name"], "Registry");
+
+    let output = sandbox.run(&["docker-credential", "list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.op_log().matches("signin").count(), 1, "{}", sandbox.op_log());
+}
+
+#[test]
+fn docker_hub_aliases_are_one_registry() {
+    let sandbox = docker_sandbox("docker_hub_aliases");
+
+    for server in &["https://index.docker.io/v1/", "index.docker.io", "registry-1.docker.io", "docker.io"] {
+        let output = sandbox.run_with_input(&["docker-credential", "get"], server);
+        assert!(output.status.success(), "{}: {}", server, stderr(&output));
+        assert!(stdout(&output).contains("\"Secret\":\"hub-secret\""), "{}: {}", server, stdout(&output));
+    }
+}
+
+#[test]
+fn docker_credential_errors_go_to_stdout() {
+    let mut sandbox = docker_sandbox("docker_errors");
+    sandbox.op_details("my", "i1", "001", json!({ "fields": [
+        { "designation": "username", "name": "username", "value": "robot" },
+    ] }));
+
+    let output = sandbox.run_with_input(&["docker-credential", "get"], "registry.example");
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "Item Registry has no password\n");
+
+    std::fs::write(sandbox.dir.join("offline"), "").unwrap();
+    let output = sandbox.run_with_input(&["docker-credential", "get"], "docker.io");
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("no such host"), "{}", stdout(&output));
+}
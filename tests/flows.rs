@@ -0,0 +1,319 @@
+mod common;
+
+use common::{Sandbox, stderr, PASSWORD, TOKEN};
+
+#[test]
+fn cold_cache_logs_in_lists_items_and_copies_the_field() {
+    let mut sandbox = Sandbox::new("cold_cache");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        // Unlock prompt, then the item, then its password
+        .answers(&[PASSWORD, "@1", "@2"]);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert_eq!(sandbox.clipboard().as_deref(), Some("s3cret"));
+    assert!(sandbox.op_log().contains("signin my --output=raw"));
+    let cache = sandbox.cache();
+    let account = &cache["accounts"][0];
+    assert_eq!(account["token"], TOKEN);
+    assert_eq!(account["items"][0]["name"], "GitHub");
+    assert_eq!(account["items"][0]["username"], "alice");
+    // Values are never cached, only their lengths
+    assert_eq!(account["items"][0]["fields"][1]["name"], "password");
+    assert_eq!(account["items"][0]["fields"][1]["value_length"], 6);
+    assert!(!sandbox.cache_contents().unwrap().contains("s3cret"));
+}
+
+#[test]
+fn warm_cache_neither_logs_in_nor_lists_items() {
+    let mut sandbox = Sandbox::new("warm_cache");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret"), ("i2", "GitLab", "bob", "t0ps3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["@2", "@2"]);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert_eq!(sandbox.clipboard().as_deref(), Some("t0ps3cret"));
+    let log = sandbox.op_log();
+    assert!(!log.contains("signin"), "{}", log);
+    assert!(!log.contains("list items"), "{}", log);
+    assert!(log.contains("get item i2"), "{}", log);
+    assert_eq!(sandbox.cache()["accounts"][0]["items"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn stale_token_logs_in_again_and_keeps_the_new_one() {
+    let mut sandbox = Sandbox::new("stale_token");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache("expired-token")
+        .answers(&["@1", "@2", PASSWORD]);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert_eq!(sandbox.clipboard().as_deref(), Some("s3cret"));
+    assert!(sandbox.op_log().contains("signin my"));
+    assert!(sandbox.dmenu_log().contains("Unlock for my (me@my.example):"));
+    assert_eq!(sandbox.cache()["accounts"][0]["token"], TOKEN);
+}
+
+#[test]
+fn wrong_password_fails_without_copying() {
+    let mut sandbox = Sandbox::new("wrong_password");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .answers(&["not the password"]);
+
+    let output = sandbox.run(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Authentication failed"), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard(), None);
+    assert_eq!(sandbox.cache_contents(), None);
+}
+
+#[test]
+fn cancelled_unlock_prompt_fails_without_writing_the_cache() {
+    let mut sandbox = Sandbox::new("cancelled_unlock");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .answers(&["!cancel"]);
+
+    let output = sandbox.run(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Must have a token"), "{}", stderr(&output));
+    assert_eq!(sandbox.cache_contents(), None);
+    assert!(!sandbox.op_log().contains("list items"));
+}
+
+#[test]
+fn cancelled_item_choice_copies_nothing() {
+    let mut sandbox = Sandbox::new("cancelled_item");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["!cancel"]);
+    let before = sandbox.cache_contents();
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard(), None);
+    assert_eq!(sandbox.cache_contents(), before);
+    assert!(!sandbox.op_log().contains("get item"));
+}
+
+#[test]
+fn cancelled_field_choice_fails_without_copying() {
+    let mut sandbox = Sandbox::new("cancelled_field");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["@1", "!cancel"]);
+
+    let output = sandbox.run(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("User cancelled field choice"), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard(), None);
+}
+
+#[test]
+fn duplicate_names_in_different_accounts_copy_the_chosen_one() {
+    let mut sandbox = Sandbox::new("duplicate_names");
+    sandbox.account("work")
+        .op_items("my", &[("i1", "GitHub", "alice", "personal")])
+        .op_items("work", &[("i2", "GitHub", "alice", "corporate")])
+        .warm_cache(TOKEN)
+        .answers(&["@2", "@2"]);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard().as_deref(), Some("corporate"));
+    assert!(sandbox.op_log().contains("get item i2"));
+}
+
+#[test]
+fn duplicate_names_are_ambiguous_to_get() {
+    let mut sandbox = Sandbox::new("duplicate_names_get");
+    sandbox.account("work")
+        .op_items("my", &[("i1", "GitHub", "alice", "personal")])
+        .op_items("work", &[("i2", "GitHub", "alice", "corporate")])
+        .warm_cache(TOKEN);
+
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(!sandbox.op_log().contains("get item"));
+
+    let output = sandbox.run(&["--account", "work", "get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "corporate\n");
+}
+
+#[test]
+fn filters_typed_one_after_another_must_all_match() {
+    let mut sandbox = Sandbox::new("filters_narrow");
+    sandbox.account("work")
+        .op_items("my", &[("i1", "GitHub", "alice", "personal")])
+        .op_items("work", &[("i2", "GitHub", "alice", "corporate")])
+        .warm_cache(TOKEN)
+        .answers(&["=@work", "=@my"]);
+
+    let output = sandbox.run(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No items match filter account work, account my"), "{}", stderr(&output));
+    // The second list only had the work item, not both accounts'
+    let log = sandbox.dmenu_log();
+    assert_eq!(log.lines().count(), 2, "{}", log);
+    assert_eq!(sandbox.clipboard(), None);
+}
+
+#[test]
+fn search_lists_matches_best_first() {
+    let mut sandbox = Sandbox::new("search");
+    sandbox.op_items("my", &[("i1", "Lighthouse", "ops", "l1ght"), ("i2", "Jenkins", "ci", "j3nk1ns"), ("i3", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN);
+
+    let output = sandbox.run(&["search", "gh"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "GitHub - alice (my/Private)\nLighthouse - ops (my/Private)\n");
+
+    let output = sandbox.run(&["search", "gh", "--json"]);
+    let list: serde_json::Value = serde_json::from_str(&common::stdout(&output)).unwrap();
+    assert_eq!(list[0]["uuid"], "i3");
+
+    // Often used items come first
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let history = serde_json::json!({ "items": { "i1": { "count": 10, "last_used": now } } });
+    std::fs::write(sandbox.dir.join("home/.config/r1pw/history.json"), history.to_string()).unwrap();
+    let output = sandbox.run(&["search", "gh"]);
+    assert_eq!(common::stdout(&output), "Lighthouse - ops (my/Private)\nGitHub - alice (my/Private)\n");
+
+    let output = sandbox.run(&["search", "zzz", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "[]\n");
+    assert!(!sandbox.op_log().contains("get item"), "{}", sandbox.op_log());
+}
+
+#[test]
+fn scripting_commands_answer_when_filters_match_nothing() {
+    let mut sandbox = Sandbox::new("filters_match_nothing");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN);
+
+    let output = sandbox.run(&["--tag", "nothing", "list", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "[]\n");
+
+    let output = sandbox.run(&["--tag", "nothing", "search", "git", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "[]\n");
+
+    let output = sandbox.run(&["--tag", "nothing", "get", "GitHub"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+
+    // Picking from nothing is still an error
+    let output = sandbox.run(&["--tag", "nothing", "--vault", "Private", "--vault", "Shared"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No items match filter tag nothing, vault Private or Shared"), "{}", stderr(&output));
+}
+
+#[test]
+fn list_and_search_keep_the_login() {
+    let mut sandbox = Sandbox::new("list_keeps_login");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .answers(&[PASSWORD]);
+
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.cache()["accounts"][0]["token"], TOKEN);
+    assert_eq!(sandbox.cache()["accounts"][0]["items"][0]["name"], "GitHub");
+
+    let output = sandbox.run(&["search", "git"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = sandbox.run(&["list", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.op_log().matches("signin").count(), 1, "{}", sandbox.op_log());
+}
+
+#[test]
+fn filters_may_follow_the_command() {
+    let mut sandbox = Sandbox::new("filters_after_command");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret"), ("i2", "Jira", "alice", "j1ra")])
+        .op_tags("my", "i2", &["work"])
+        .warm_cache(TOKEN);
+
+    // As the README has it
+    let output = sandbox.run(&["list", "--tag", "work", "--ndjson"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let names = common::stdout(&output).lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["name"].as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Jira"]);
+
+    let output = sandbox.run(&["--tag", "work", "list", "--ndjson"]);
+    assert_eq!(common::stdout(&output).lines().count(), 1, "{}", common::stdout(&output));
+}
+
+#[test]
+fn corrupt_cache_is_reported_and_left_alone() {
+    let mut sandbox = Sandbox::new("corrupt_cache");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")]);
+    sandbox.write_cache("{\"accounts\": [");
+
+    let output = sandbox.run(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Error de-serialising cache file"), "{}", stderr(&output));
+    assert_eq!(sandbox.cache_contents().as_deref(), Some("{\"accounts\": ["));
+    assert_eq!(sandbox.clipboard(), None);
+}
+
+#[test]
+fn hung_op_times_out_without_asking_to_log_in() {
+    let mut sandbox = Sandbox::new("hung_op");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["@1", "@2", PASSWORD]);
+    std::fs::write(sandbox.dir.join("delay"), "10").unwrap();
+
+    let started = std::time::Instant::now();
+    let output = sandbox.run(&["--timeout", "1"]);
+    assert!(!output.status.success());
+    assert!(started.elapsed().as_secs() < 3);
+    assert!(stderr(&output).contains("op get item i1 timed out after 1s"), "{}", stderr(&output));
+    assert!(!sandbox.dmenu_log().contains("Unlock"), "{}", sandbox.dmenu_log());
+    assert_eq!(sandbox.clipboard(), None);
+}
+
+#[test]
+fn offline_offers_cached_metadata_without_asking_to_log_in() {
+    let mut sandbox = Sandbox::new("offline");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache("expired-token")
+        // The item, its password, then the cached username instead
+        .answers(&["@1", "@2", "@1"]);
+    std::fs::write(sandbox.dir.join("offline"), "").unwrap();
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard().as_deref(), Some("alice"));
+    assert!(!sandbox.op_log().contains("signin"), "{}", sandbox.op_log());
+    assert!(sandbox.dmenu_log().contains("-p Offline, secrets unavailable:"), "{}", sandbox.dmenu_log());
+
+    // The next run says so from the start, until op gets through again
+    std::fs::remove_file(sandbox.dir.join("offline")).unwrap();
+    std::fs::remove_file(sandbox.dir.join("dmenu.log")).unwrap();
+    sandbox.warm_cache(TOKEN).answers(&["@1", "@2"]);
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.dmenu_log().lines().next().unwrap().contains("-p Offline"), "{}", sandbox.dmenu_log());
+    assert_eq!(sandbox.clipboard().as_deref(), Some("s3cret"));
+    assert!(!sandbox.dir.join("home/.config/r1pw/offline").exists());
+}
+
+#[test]
+fn offline_cold_cache_reports_the_network() {
+    let mut sandbox = Sandbox::new("offline_cold");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .answers(&[PASSWORD]);
+    std::fs::write(sandbox.dir.join("offline"), "").unwrap();
+
+    let output = sandbox.run(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("op can't reach 1Password"), "{}", stderr(&output));
+}
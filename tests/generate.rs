@@ -0,0 +1,139 @@
+mod common;
+
+use common::{Sandbox, stderr, stdout};
+
+fn generated(sandbox: &Sandbox, args: &[&str]) -> String {
+    let output = sandbox.run(&[&["generate", "--print"], args].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("bits of entropy"), "{}", stderr(&output));
+    stdout(&output).trim_end().to_owned()
+}
+
+#[test]
+fn default_password_has_every_kind_of_character() {
+    let sandbox = Sandbox::new("generate_default");
+    for _ in 0..10 {
+        let password = generated(&sandbox, &[]);
+        assert_eq!(password.chars().count(), 24, "{}", password);
+        assert!(password.chars().any(|c| c.is_ascii_lowercase()), "{}", password);
+        assert!(password.chars().any(|c| c.is_ascii_uppercase()), "{}", password);
+        assert!(password.chars().any(|c| c.is_ascii_digit()), "{}", password);
+        assert!(password.chars().any(|c| c.is_ascii_punctuation()), "{}", password);
+    }
+    assert_ne!(generated(&sandbox, &[]), generated(&sandbox, &[]));
+}
+
+#[test]
+fn presets() {
+    let sandbox = Sandbox::new("generate_presets");
+    for _ in 0..10 {
+        let pin = generated(&sandbox, &["--preset", "pin6"]);
+        assert!(pin.len() == 6 && pin.chars().all(|c| c.is_ascii_digit()), "{}", pin);
+
+        let legacy = generated(&sandbox, &["--preset", "legacy-16"]);
+        assert_eq!(legacy.len(), 16);
+        assert!(legacy.chars().all(|c| c.is_ascii_alphanumeric() && !"0Oo1Il".contains(c)), "{}", legacy);
+
+        let aws = generated(&sandbox, &["--preset", "aws"]);
+        assert_eq!(aws.len(), 32);
+        assert!(aws.chars().all(|c| c.is_ascii_alphanumeric() || "!@#$%^&*()_+-=[]{}|'".contains(c)), "{}", aws);
+    }
+    // Options given narrow a preset further
+    assert_eq!(generated(&sandbox, &["--preset", "pin6", "--length", "8"]).len(), 8);
+}
+
+#[test]
+fn configured_presets_add_to_and_replace_built_in_ones() {
+    let sandbox = Sandbox::new("generate_configured");
+    sandbox.write_config(r#"{ "generate": { "presets": {
+        "wifi": { "words": 4, "separator": " " },
+        "pin6": { "length": 4, "lowercase": false, "uppercase": false, "symbols": false }
+    } } }"#);
+
+    let wifi = generated(&sandbox, &["--preset", "wifi"]);
+    assert_eq!(wifi.split(' ').count(), 4, "{}", wifi);
+    assert_eq!(generated(&sandbox, &["--preset", "pin6"]).len(), 4);
+
+    let output = sandbox.run(&["generate", "--preset", "nope"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("choose from aws, legacy-16, pin6, wifi"), "{}", stderr(&output));
+}
+
+#[test]
+fn passphrase_uses_the_bundled_wordlist() {
+    let sandbox = Sandbox::new("generate_passphrase");
+    let words = include_str!("../src/eff_large_wordlist.txt").lines()
+        .map(|line| line.split('\t').nth(1).unwrap())
+        .collect::<Vec<_>>();
+
+    let output = sandbox.run(&["generate", "--print", "--words", "6"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let passphrase = stdout(&output).trim_end().to_owned();
+    assert_eq!(passphrase.split('-').count(), 6, "{}", passphrase);
+    assert!(passphrase.split('-').all(|w| words.contains(&w)), "{}", passphrase);
+    // log2(7776) is 12.9 bits a word
+    assert!(stderr(&output).contains("About 77 bits of entropy"), "{}", stderr(&output));
+}
+
+#[test]
+fn copies_to_the_clipboard_without_printing() {
+    let sandbox = Sandbox::new("generate_copy");
+
+    let output = sandbox.run(&["generate", "--length", "20"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(sandbox.clipboard().unwrap().len(), 20);
+    assert!(stderr(&output).contains("Copied a 20 character password"), "{}", stderr(&output));
+    assert!(sandbox.op_log().is_empty());
+}
+
+#[test]
+fn impossible_recipes_fail() {
+    let sandbox = Sandbox::new("generate_impossible");
+
+    let output = sandbox.run(&["generate", "--no-lowercase", "--no-uppercase", "--no-digits", "--no-symbols"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No characters left"), "{}", stderr(&output));
+
+    let output = sandbox.run(&["generate", "--length", "3"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("needs at least 4 characters"), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard(), None);
+}
+
+#[test]
+fn options_conflicting_with_the_preset_fail() {
+    let sandbox = Sandbox::new("generate_conflicts");
+    sandbox.write_config(r#"{ "generate": { "presets": { "wifi": { "words": 4 } } } }"#);
+
+    let output = sandbox.run(&["generate", "--preset", "pin6", "--symbols", "!"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Preset pin6 leaves out symbols"), "{}", stderr(&output));
+
+    let output = sandbox.run(&["generate", "--preset", "wifi", "--length", "12"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Preset wifi makes a passphrase"), "{}", stderr(&output));
+
+    let output = sandbox.run(&["generate", "--preset", "aws", "--words", "4"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("preset aws makes a password"), "{}", stderr(&output));
+
+    let output = sandbox.run(&["generate", "--separator", " "]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--separator only applies to passphrases"), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard(), None);
+}
+
+#[test]
+fn entropy_counts_only_passwords_with_every_kind_of_character() {
+    let sandbox = Sandbox::new("generate_entropy");
+
+    // 4 characters of 89 would be 25.9 bits, but only those with one of each kind are made
+    let output = sandbox.run(&["generate", "--print", "--length", "4"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("About 22 bits of entropy"), "{}", stderr(&output));
+
+    // A single kind of character is unrestricted, 6 digits are 19.9 bits
+    let output = sandbox.run(&["generate", "--print", "--preset", "pin6"]);
+    assert!(stderr(&output).contains("About 19 bits of entropy"), "{}", stderr(&output));
+}
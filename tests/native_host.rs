@@ -0,0 +1,108 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::process::{Child, Stdio};
+use serde_json::{json, Value};
+use common::{Sandbox, TOKEN};
+
+/// `r1pw native-host` as a browser runs it
+struct Host {
+    process: Child,
+}
+
+impl Host {
+    fn start(sandbox: &Sandbox) -> Host {
+        let process = sandbox.command(&["native-host"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        Host { process }
+    }
+
+    fn request(&mut self, message: Value) -> Value {
+        let message = message.to_string();
+        let stdin = self.process.stdin.as_mut().unwrap();
+        stdin.write_all(&(message.len() as u32).to_ne_bytes()).unwrap();
+        stdin.write_all(message.as_bytes()).unwrap();
+        stdin.flush().unwrap();
+        let stdout = self.process.stdout.as_mut().unwrap();
+        let mut length = [0u8; 4];
+        stdout.read_exact(&mut length).unwrap();
+        let mut reply = vec![0u8; u32::from_ne_bytes(length) as usize];
+        stdout.read_exact(&mut reply).unwrap();
+        serde_json::from_slice(&reply).unwrap()
+    }
+
+    fn credentials(&mut self, origin: &str) -> Value {
+        self.request(json!({ "action": "get-credentials", "origin": origin, "id": 1 }))
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        drop(self.process.stdin.take());
+        let _ = self.process.wait();
+    }
+}
+
+fn sites(name: &str) -> Sandbox {
+    let mut sandbox = Sandbox::new(name);
+    sandbox.op_items("my", &[("i1", "Shop", "alice", "s3cret")])
+        .op_url("my", "i1", "https://shop.example.com")
+        .warm_cache(TOKEN);
+    sandbox
+}
+
+#[test]
+fn other_sites_of_the_domain_get_nothing() {
+    let sandbox = sites("native_host_siblings");
+    let mut host = Host::start(&sandbox);
+
+    assert_eq!(host.credentials("https://evil.example.com"), json!({ "id": 1, "status": "not-found" }));
+    assert_eq!(host.credentials("https://example.com"), json!({ "id": 1, "status": "not-found" }));
+    assert_eq!(host.credentials("http://shop.example.com"), json!({ "id": 1, "status": "not-found" }));
+    assert!(sandbox.dmenu_log().is_empty(), "{}", sandbox.dmenu_log());
+    assert!(!sandbox.op_log().contains("get item"), "{}", sandbox.op_log());
+}
+
+#[test]
+fn confirmed_item_is_sent() {
+    let mut sandbox = sites("native_host_ok");
+    sandbox.answers(&["@1"]);
+    let mut host = Host::start(&sandbox);
+
+    assert_eq!(host.request(json!({ "action": "ping", "id": "p" })), json!({ "id": "p", "status": "ok" }));
+    let reply = host.credentials("https://shop.example.com/cart");
+    assert_eq!(reply, json!({ "id": 1, "status": "ok", "username": "alice", "password": "s3cret" }));
+    assert!(sandbox.dmenu_log().contains("Fill in https://shop.example.com/cart?"), "{}", sandbox.dmenu_log());
+}
+
+#[test]
+fn cancelled_confirmation_is_denied() {
+    let mut sandbox = sites("native_host_denied");
+    sandbox.answers(&["!cancel"]);
+    let mut host = Host::start(&sandbox);
+
+    assert_eq!(host.credentials("https://shop.example.com"), json!({ "id": 1, "status": "denied" }));
+    assert!(!sandbox.op_log().contains("get item"), "{}", sandbox.op_log());
+}
+
+#[test]
+fn failures_are_replied_and_the_host_keeps_serving() {
+    let mut sandbox = sites("native_host_error");
+    std::fs::remove_file(sandbox.dir.join("item-i1.json")).unwrap();
+    sandbox.answers(&["@1"]);
+    let mut host = Host::start(&sandbox);
+
+    // op failing looks like an expired login, and the unlock prompt is left unanswered
+    let reply = host.credentials("https://shop.example.com");
+    assert_eq!(reply["status"], "error");
+    assert!(reply["message"].as_str().unwrap().contains("Login cancelled"), "{}", reply);
+
+    let reply = host.request(json!({ "action": "delete-everything", "id": 2 }));
+    assert_eq!(reply["status"], "error");
+    assert!(reply["message"].as_str().unwrap().starts_with("Bad request"), "{}", reply);
+
+    assert_eq!(host.request(json!({ "action": "ping" })), json!({ "status": "ok" }));
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{Sandbox, stderr, stdout, PASSWORD, TOKEN};
+
+#[test]
+fn failed_getpin_is_reported_and_serving_goes_on() {
+    let mut sandbox = Sandbox::new("pinentry_getpin_fails");
+    sandbox.op_items("my", &[("i1", "GPG key", "", "passphrase")])
+        .warm_cache(TOKEN)
+        .answers(&["@1"]);
+    std::fs::write(sandbox.dir.join("offline"), "").unwrap();
+
+    let output = sandbox.run_with_input(&["pinentry"], "SETDESC Unlock%0Akey\nGETPIN\nGETINFO flavor\nBYE\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let lines = stdout(&output).lines().map(|l| l.to_owned()).collect::<Vec<_>>();
+    assert_eq!(lines[0], "OK Pleased to meet you");
+    assert_eq!(lines[1], "OK");
+    assert!(lines[2].starts_with("ERR 83886081 ") && lines[2].ends_with(" <Pinentry>"), "{}", lines[2]);
+    assert_eq!(lines[3..], ["D r1pw", "OK", "OK closing connection"]);
+}
+
+#[test]
+fn greets_before_unlocking_and_unlocks_on_getpin() {
+    let mut sandbox = Sandbox::new("pinentry_greeting");
+    sandbox.op_items("my", &[("i1", "GPG key", "", "passphrase")]);
+
+    // Nothing asked for a passphrase, so nothing is unlocked
+    let output = sandbox.run_with_input(&["pinentry"], "OPTION ttyname=/dev/pts/1\nGETINFO pid\nBYE\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("OK Pleased to meet you\nOK\nD "), "{}", stdout(&output));
+    assert!(sandbox.op_log().is_empty(), "{}", sandbox.op_log());
+    assert!(sandbox.dmenu_log().is_empty(), "{}", sandbox.dmenu_log());
+
+    sandbox.answers(&[PASSWORD, "@1"]);
+    let output = sandbox.run_with_input(&["pinentry"], "GETPIN\nGETPIN\nBYE\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "OK Pleased to meet you\nD passphrase\nOK\nERR 83886179 Operation cancelled <Pinentry>\nOK closing connection\n");
+    // Unlocked and listed once, for the first GETPIN
+    assert_eq!(sandbox.op_log().matches("signin").count(), 1, "{}", sandbox.op_log());
+    assert_eq!(sandbox.op_log().matches("list items").count(), 1, "{}", sandbox.op_log());
+}
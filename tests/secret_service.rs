@@ -0,0 +1,191 @@
+mod common;
+
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::process::Child;
+use std::time::{Duration, Instant};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use zbus::blocking::Connection;
+use zbus::blocking::fdo::DBusProxy;
+use zbus::zvariant::{DynamicType, ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use common::{Bus, Sandbox, TOKEN};
+
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const GITHUB: &str = "/org/freedesktop/secrets/collection/my/i1";
+const GITLAB: &str = "/org/freedesktop/secrets/collection/my/i2";
+
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// `r1pw secret-service` on a private session bus, over cached items whose fields were never fetched
+struct Provider {
+    sandbox: Sandbox,
+    process: Child,
+    connection: Connection,
+    // Dropped last, the provider needs it until killed
+    _bus: Bus,
+}
+
+impl Provider {
+    fn start(name: &str) -> Option<Provider> {
+        Provider::start_with(name, |_| {})
+    }
+
+    /// Started over the two logins, and whatever else `setup` adds
+    fn start_with<F: FnOnce(&mut Sandbox)>(name: &str, setup: F) -> Option<Provider> {
+        let bus = match Bus::start() {
+            Some(bus) => bus,
+            None => {
+                eprintln!("dbus-daemon not found, skipping");
+                return None
+            },
+        };
+        let mut sandbox = Sandbox::new(name);
+        sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret"), ("i2", "GitLab", "bob", "t0ps3cret")]);
+        setup(&mut sandbox);
+        sandbox.warm_cache(TOKEN);
+        let mut cache = sandbox.cache();
+        for item in cache["accounts"][0]["items"].as_array_mut().unwrap() {
+            item["fields"] = serde_json::json!([]);
+        }
+        sandbox.write_cache(&cache.to_string());
+        let process = sandbox.command(&["secret-service"])
+            .env("DBUS_SESSION_BUS_ADDRESS", &bus.address)
+            .spawn()
+            .unwrap();
+        let connection = zbus::blocking::connection::Builder::address(bus.address.as_str()).unwrap()
+            .build()
+            .unwrap();
+        let dbus = DBusProxy::new(&connection).unwrap();
+        let started = Instant::now();
+        while !dbus.name_has_owner("org.freedesktop.secrets".try_into().unwrap()).unwrap() {
+            assert!(started.elapsed() < Duration::from_secs(3), "provider never took its name");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Some(Provider { sandbox, process, connection, _bus: bus })
+    }
+
+    fn call<B, R>(&self, path: &str, interface: &str, method: &str, body: &B) -> zbus::Result<R>
+    where
+        B: serde::Serialize + DynamicType,
+        R: DeserializeOwned + zbus::zvariant::Type,
+    {
+        let reply = self.connection.call_method(Some("org.freedesktop.secrets"), path, Some(interface), method, body)?;
+        reply.body().deserialize()
+    }
+
+    fn service<B, R>(&self, method: &str, body: &B) -> zbus::Result<R>
+    where
+        B: serde::Serialize + DynamicType,
+        R: DeserializeOwned + zbus::zvariant::Type,
+    {
+        self.call(SERVICE_PATH, "org.freedesktop.Secret.Service", method, body)
+    }
+
+    fn open_session(&self) -> OwnedObjectPath {
+        let (_, session): (OwnedValue, OwnedObjectPath) = self.service("OpenSession", &("plain", Value::from(""))).unwrap();
+        session
+    }
+
+    fn secrets(&self, items: &[&str], session: &OwnedObjectPath) -> HashMap<OwnedObjectPath, Secret> {
+        let items = items.iter().map(|i| ObjectPath::try_from(*i).unwrap()).collect::<Vec<_>>();
+        self.service("GetSecrets", &(items, session)).unwrap()
+    }
+}
+
+impl Drop for Provider {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn secret_value(secrets: &HashMap<OwnedObjectPath, Secret>, path: &str) -> String {
+    let (_, (_, _, value, _)) = secrets.iter().find(|(p, _)| p.as_str() == path).expect("secret returned");
+    String::from_utf8(value.clone()).unwrap()
+}
+
+fn not_supported(result: zbus::Result<OwnedObjectPath>) -> bool {
+    matches!(result, Err(zbus::Error::MethodError(name, _, _)) if name.as_str() == "org.freedesktop.DBus.Error.NotSupported")
+}
+
+#[test]
+fn search_items_matches_attributes() {
+    if let Some(provider) = Provider::start("secret_service_search") {
+        let attributes = HashMap::from([("server", "gitlab.example"), ("user", "bob")]);
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = provider.service("SearchItems", &attributes).unwrap();
+        assert_eq!(unlocked.iter().map(|p| p.as_str()).collect::<Vec<_>>(), vec![GITLAB]);
+        assert!(locked.is_empty());
+
+        let attributes = HashMap::from([("server", "gitlab.example"), ("user", "alice")]);
+        let (unlocked, _): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = provider.service("SearchItems", &attributes).unwrap();
+        assert!(unlocked.is_empty());
+        // Nothing is fetched to search
+        assert!(!provider.sandbox.op_log().contains("get item"), "{}", provider.sandbox.op_log());
+    }
+}
+
+#[test]
+fn get_secrets_reads_passwords_and_keeps_every_read_cached() {
+    if let Some(provider) = Provider::start("secret_service_secrets") {
+        let session = provider.open_session();
+        assert_eq!(secret_value(&provider.secrets(&[GITHUB], &session), GITHUB), "s3cret");
+        assert_eq!(secret_value(&provider.secrets(&[GITLAB], &session), GITLAB), "t0ps3cret");
+
+        // Reading the second item didn't forget the fields the first read found
+        let cache = provider.sandbox.cache();
+        let items = cache["accounts"][0]["items"].as_array().unwrap();
+        assert_eq!(items[0]["fields"][1]["value_length"], 6);
+        assert_eq!(items[1]["fields"][1]["value_length"], 9);
+        assert!(!provider.sandbox.cache_contents().unwrap().contains("s3cret"));
+    }
+}
+
+#[test]
+fn other_items_give_their_concealed_field() {
+    let provider = Provider::start_with("secret_service_sections", |sandbox| {
+        sandbox.op_items("my", &[("i3", "Orders DB", "", ""), ("i4", "Wifi", "", "")])
+            .op_details("my", "i3", "102", json!({ "sections": [{ "name": "", "title": "", "fields": [
+                { "k": "menu", "n": "database_type", "t": "type", "v": "postgresql" },
+                { "k": "string", "n": "hostname", "t": "server", "v": "db.internal" },
+                { "k": "concealed", "n": "password", "t": "password", "v": "pg-s3cret" },
+            ] }] }))
+            .op_details("my", "i4", "003", json!({ "notesPlain": "SSID: home" }));
+    });
+    if let Some(provider) = provider {
+        let session = provider.open_session();
+        let database = "/org/freedesktop/secrets/collection/my/i3";
+        assert_eq!(secret_value(&provider.secrets(&[database], &session), database), "pg-s3cret");
+
+        // Without a password there is no secret to give, rather than the first field
+        let note = ObjectPath::try_from("/org/freedesktop/secrets/collection/my/i4").unwrap();
+        let result: zbus::Result<HashMap<OwnedObjectPath, Secret>> = provider.service("GetSecrets", &(vec![note], &session));
+        assert!(result.map_or(true, |secrets| secrets.is_empty()));
+    }
+}
+
+#[test]
+fn get_secrets_needs_an_open_session() {
+    if let Some(provider) = Provider::start("secret_service_no_session") {
+        let session = ObjectPath::try_from("/org/freedesktop/secrets/session/s99").unwrap();
+        let items = vec![ObjectPath::try_from(GITHUB).unwrap()];
+        let result: zbus::Result<HashMap<OwnedObjectPath, Secret>> = provider.service("GetSecrets", &(items, session));
+        assert!(matches!(result, Err(zbus::Error::MethodError(name, _, _)) if name.as_str() == "org.freedesktop.Secret.Error.NoSession"));
+        assert!(!provider.sandbox.op_log().contains("get item"), "{}", provider.sandbox.op_log());
+    }
+}
+
+#[test]
+fn changes_are_refused() {
+    if let Some(provider) = Provider::start("secret_service_read_only") {
+        let properties: HashMap<&str, Value> = HashMap::new();
+        let result: zbus::Result<(OwnedObjectPath, OwnedObjectPath)> = provider.service("CreateCollection", &(properties, ""));
+        assert!(not_supported(result.map(|(collection, _)| collection)));
+        assert!(not_supported(provider.call(GITHUB, "org.freedesktop.Secret.Item", "Delete", &())));
+        let collection = "/org/freedesktop/secrets/collection/my";
+        assert!(not_supported(provider.call(collection, "org.freedesktop.Secret.Collection", "Delete", &())));
+        // The process keeps serving
+        let session = provider.open_session();
+        assert_eq!(secret_value(&provider.secrets(&[GITHUB], &session), GITHUB), "s3cret");
+    }
+}
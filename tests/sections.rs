@@ -0,0 +1,134 @@
+mod common;
+
+use serde_json::{json, Value};
+use common::{Sandbox, stderr, stdout, PASSWORD, TOKEN};
+
+fn database(name: &str) -> Sandbox {
+    let mut sandbox = Sandbox::new(name);
+    sandbox.op_items("my", &[("i1", "Orders DB", "", ""), ("i2", "Wifi", "", "")])
+        .op_details("my", "i1", "102", json!({
+            "notesPlain": "Rotated quarterly\nAsk ops first",
+            "sections": [
+                { "name": "", "title": "", "fields": [
+                    { "k": "string", "n": "hostname", "t": "server", "v": "db.internal" },
+                    { "k": "string", "n": "port", "t": "port", "v": 5432 },
+                    { "k": "concealed", "n": "password", "t": "password", "v": "pg-s3cret" },
+                    { "k": "string", "n": "sid", "t": "SID", "v": "" },
+                ] },
+                { "name": "replica", "title": "Replica", "fields": [
+                    { "k": "concealed", "n": "password", "t": "password", "v": "replica-s3cret" },
+                    { "k": "monthYear", "n": "expires", "t": "expires", "v": 202712 },
+                ] },
+            ],
+        }))
+        .op_details("my", "i2", "003", json!({ "notesPlain": "SSID: home\nKey: hunter3" }));
+    sandbox
+}
+
+#[test]
+fn section_fields_and_notes_are_listed() {
+    let mut sandbox = database("sections");
+    sandbox.answers(&[PASSWORD]);
+
+    let output = sandbox.run(&["get", "Orders DB", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let item: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(item["category"], "Database");
+    let fields = item["fields"].as_array().unwrap().iter()
+        .map(|f| (f["section"].as_str(), f["name"].as_str().unwrap(), f["value"].as_str().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(fields, vec![
+        (None, "server", "db.internal"),
+        (None, "port", "5432"),
+        (None, "password", "pg-s3cret"),
+        (Some("Replica"), "password", "replica-s3cret"),
+        (Some("Replica"), "expires", "2027/12"),
+        (None, "notes", "Rotated quarterly\nAsk ops first"),
+    ]);
+
+    let output = sandbox.run(&["get", "Wifi", "--field", "notes"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "SSID: home\nKey: hunter3\n");
+}
+
+#[test]
+fn first_concealed_field_is_the_password() {
+    let mut sandbox = database("sections_password");
+    sandbox.answers(&[PASSWORD]);
+
+    let output = sandbox.run(&["get", "Orders DB", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let item: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    let designations = item["fields"].as_array().unwrap().iter()
+        .map(|f| f["designation"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(designations, ["", "", "password", "", "", "notes"]);
+}
+
+#[test]
+fn list_shows_the_category() {
+    let mut sandbox = database("sections_category");
+    sandbox.answers(&[PASSWORD]);
+
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Orders DB (my/Private) [Database]\nWifi (my/Private) [Secure Note]\n");
+}
+
+#[test]
+fn items_cached_without_categories_are_listed_again() {
+    let mut sandbox = database("sections_old_cache");
+    sandbox.warm_cache(TOKEN);
+    // As written before categories were recorded
+    let mut cache = sandbox.cache();
+    cache["accounts"][0]["items_version"] = 2.into();
+    sandbox.write_cache(&cache.to_string());
+
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Orders DB (my/Private) [Database]\nWifi (my/Private) [Secure Note]\n");
+    assert!(sandbox.op_log().contains("list items --account my"), "{}", sandbox.op_log());
+}
+
+#[test]
+fn only_logins_have_usernames() {
+    let mut sandbox = Sandbox::new("sections_subtitle");
+    // op shows the start of a note where a login has its username
+    sandbox.op_items("my", &[("n1", "Recovery codes", "Rotated quarterly", "")])
+        .op_details("my", "n1", "003", json!({ "notesPlain": "Rotated quarterly\n1234-5678" }))
+        .answers(&[PASSWORD]);
+
+    let output = sandbox.run(&["list", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let list: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(list[0]["username"], Value::Null);
+
+    // Items cached with it as their username are listed again
+    sandbox.warm_cache(TOKEN);
+    let mut cache = sandbox.cache();
+    cache["accounts"][0]["items"][0]["username"] = "Rotated quarterly".into();
+    cache["accounts"][0]["items_version"] = 3.into();
+    sandbox.write_cache(&cache.to_string());
+    let output = sandbox.run(&["list"]);
+    assert_eq!(stdout(&output), "Recovery codes (my/Private) [Secure Note]\n");
+    assert_eq!(sandbox.cache()["accounts"][0]["items"][0]["username"], Value::Null);
+}
+
+#[test]
+fn chosen_section_field_is_copied() {
+    let mut sandbox = database("sections_copy");
+    // Unlock, the item, then the replica's password
+    sandbox.answers(&[PASSWORD, "@1", "@4"]);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard().as_deref(), Some("replica-s3cret"));
+
+    // Once cached, fields of the same name are told apart by their section
+    sandbox.answers(&["@1", "@4"]);
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard().as_deref(), Some("replica-s3cret"));
+    let fields = &sandbox.cache()["accounts"][0]["items"][0]["fields"];
+    assert_eq!(fields[3]["section"], "Replica");
+}
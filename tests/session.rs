@@ -0,0 +1,156 @@
+mod common;
+
+use std::process::Stdio;
+use std::time::Duration;
+use common::{Sandbox, stderr, PASSWORD, TOKEN};
+
+#[test]
+fn lock_drops_tokens_and_values_but_keeps_items() {
+    let mut sandbox = Sandbox::new("lock");
+    sandbox.account("work")
+        .op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .op_items("work", &[("i2", "Jira", "alice", "corporate")])
+        .warm_cache(TOKEN);
+    sandbox.write_config(r#"{ "value_cache": { "items": ["GitHub"] } }"#);
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.dir.join("home/.config/r1pw/values.json").exists());
+
+    let output = sandbox.run(&["lock"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let cache = sandbox.cache();
+    for account in cache["accounts"].as_array().unwrap() {
+        assert!(account["token"].is_null(), "{}", cache);
+        assert_eq!(account["items"].as_array().unwrap().len(), 1);
+    }
+    assert!(!sandbox.cache_contents().unwrap().contains(TOKEN));
+    assert!(!sandbox.dir.join("home/.config/r1pw/values.json").exists());
+    assert!(!sandbox.op_log().contains("signout"), "{}", sandbox.op_log());
+
+    // Every account must be unlocked again
+    sandbox.answers(&[PASSWORD, PASSWORD]);
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "s3cret\n");
+    let log = sandbox.dmenu_log();
+    assert!(log.contains("Unlock for my") && log.contains("Unlock for work"), "{}", log);
+}
+
+#[test]
+fn lock_holds_against_a_run_already_under_way() {
+    let mut sandbox = Sandbox::new("lock_during_run");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN);
+    std::fs::write(sandbox.dir.join("delay"), "1").unwrap();
+
+    // op takes a second to answer, long enough to lock meanwhile
+    let run = sandbox.command(&["get", "GitHub", "--field", "password"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    let lock = sandbox.run(&["lock"]);
+    assert!(lock.status.success(), "{}", stderr(&lock));
+    let run = run.wait_with_output().unwrap();
+    assert!(run.status.success(), "{}", stderr(&run));
+    assert_eq!(common::stdout(&run), "s3cret\n");
+
+    // The run finished after the lock, and left the token out
+    assert!(sandbox.cache()["accounts"][0]["token"].is_null(), "{}", sandbox.cache());
+    assert_eq!(sandbox.cache()["accounts"][0]["items"][0]["fields"][1]["value_length"], 6);
+}
+
+#[test]
+fn lock_without_a_cache_does_nothing() {
+    let sandbox = Sandbox::new("lock_cold");
+
+    let output = sandbox.run(&["lock"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.cache_contents(), None);
+    assert_eq!(sandbox.dmenu_log(), "");
+}
+
+#[test]
+fn logout_signs_out_of_one_account() {
+    let mut sandbox = Sandbox::new("logout");
+    sandbox.account("work")
+        .op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .op_items("work", &[("i2", "Jira", "alice", "corporate")])
+        .warm_cache(TOKEN);
+
+    let output = sandbox.run(&["logout", "work"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.op_log().contains(&format!("signout --account work --session {}", TOKEN)), "{}", sandbox.op_log());
+    assert!(!sandbox.op_log().contains("--account my"), "{}", sandbox.op_log());
+    let cache = sandbox.cache();
+    assert_eq!(cache["accounts"][0]["token"], TOKEN);
+    assert!(cache["accounts"][1]["token"].is_null());
+}
+
+#[test]
+fn logout_with_an_expired_token_still_drops_it() {
+    let mut sandbox = Sandbox::new("logout_expired");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache("expired-token");
+
+    let output = sandbox.run(&["logout"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.op_log().contains("signout --account my"), "{}", sandbox.op_log());
+    assert!(sandbox.cache()["accounts"][0]["token"].is_null());
+}
+
+#[test]
+fn logout_offline_drops_the_token_but_fails() {
+    let mut sandbox = Sandbox::new("logout_offline");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN);
+    std::fs::write(sandbox.dir.join("offline"), "").unwrap();
+
+    let output = sandbox.run(&["logout"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("could not sign out of my"), "{}", stderr(&output));
+    assert!(sandbox.cache()["accounts"][0]["token"].is_null());
+}
+
+#[test]
+fn running_native_host_forgets_its_tokens_when_locked() {
+    use std::io::{Read, Write};
+    use std::process::Stdio;
+
+    fn request(host: &mut std::process::Child) -> String {
+        let message = br#"{"action":"get-credentials","origin":"https://github.example"}"#;
+        let stdin = host.stdin.as_mut().unwrap();
+        stdin.write_all(&(message.len() as u32).to_ne_bytes()).unwrap();
+        stdin.write_all(message).unwrap();
+        stdin.flush().unwrap();
+        let stdout = host.stdout.as_mut().unwrap();
+        let mut length = [0u8; 4];
+        stdout.read_exact(&mut length).unwrap();
+        let mut reply = vec![0u8; u32::from_ne_bytes(length) as usize];
+        stdout.read_exact(&mut reply).unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    let mut sandbox = Sandbox::new("lock_native_host");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["@1", "@1", PASSWORD]);
+    let mut host = sandbox.command(&["native-host"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    assert!(request(&mut host).contains("s3cret"));
+    assert!(!sandbox.op_log().contains("signin"), "{}", sandbox.op_log());
+
+    let output = sandbox.run(&["lock"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(request(&mut host).contains("s3cret"));
+    assert!(sandbox.op_log().contains("signin my"), "{}", sandbox.op_log());
+    assert!(sandbox.dmenu_log().contains("Unlock for my"), "{}", sandbox.dmenu_log());
+
+    drop(host.stdin.take());
+    assert!(host.wait().unwrap().success());
+}
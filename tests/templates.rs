@@ -0,0 +1,55 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use common::{Sandbox, stderr, stdout, TOKEN};
+
+fn template(sandbox: &Sandbox, contents: &str) -> String {
+    let path = sandbox.dir.join("config.tpl");
+    fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn rendered_file_is_private() {
+    let mut sandbox = Sandbox::new("inject_private");
+    sandbox.op_items("my", &[("i1", "Postgres", "app", "pg-s3cret")])
+        .warm_cache(TOKEN);
+    let input = template(&sandbox, "user: {{ r1pw://my/Postgres/username }}\npass: {{ r1pw://my/Postgres/password }}\n");
+    let output = sandbox.dir.join("config.yml");
+    // Left readable by others before, it's tightened rather than kept
+    fs::write(&output, "old").unwrap();
+    fs::set_permissions(&output, fs::Permissions::from_mode(0o644)).unwrap();
+
+    let result = sandbox.run(&["inject", "-i", &input, "-o", output.to_str().unwrap()]);
+    assert!(result.status.success(), "{}", stderr(&result));
+    assert_eq!(stdout(&result), "");
+    assert_eq!(fs::read_to_string(&output).unwrap(), "user: app\npass: pg-s3cret\n");
+    assert_eq!(fs::metadata(&output).unwrap().permissions().mode() & 0o777, 0o600);
+
+    let fresh = sandbox.dir.join("fresh.yml");
+    let result = sandbox.run(&["inject", "-i", &input, "-o", fresh.to_str().unwrap()]);
+    assert!(result.status.success(), "{}", stderr(&result));
+    assert_eq!(fs::metadata(&fresh).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[test]
+fn bad_references_are_reported_by_line() {
+    let mut sandbox = Sandbox::new("inject_bad_references");
+    sandbox.op_items("my", &[("i1", "Postgres", "app", "pg-s3cret")])
+        .warm_cache(TOKEN);
+    let input = template(&sandbox, "a: {{ r1pw://my/Postgres }}\nb: {{ r1pw://my/Postgres/password }}\nc: {{ r1pw://my/Mysql/password }}\n");
+    let output = sandbox.dir.join("config.yml");
+
+    let result = sandbox.run(&["inject", "-i", &input, "-o", output.to_str().unwrap()]);
+    assert!(!result.status.success());
+    assert!(stderr(&result).contains("line 1: Bad reference"), "{}", stderr(&result));
+    assert!(!output.exists());
+
+    let input = template(&sandbox, "b: {{ r1pw://my/Postgres/password }}\nc: {{ r1pw://my/Mysql/password }}\n");
+    let result = sandbox.run(&["inject", "-i", &input, "-o", output.to_str().unwrap()]);
+    assert!(!result.status.success());
+    assert!(stderr(&result).contains("line 2: "), "{}", stderr(&result));
+    assert!(stderr(&result).contains("Mysql"), "{}", stderr(&result));
+    assert!(!output.exists());
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::{Sandbox, stderr, TOKEN};
+
+fn values(sandbox: &Sandbox) -> Option<String> {
+    std::fs::read_to_string(sandbox.dir.join("home/.config/r1pw/values.json")).ok()
+}
+
+#[test]
+fn cached_item_copies_offline_without_asking_op() {
+    let mut sandbox = Sandbox::new("value_cache_offline");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret"), ("i2", "GitLab", "bob", "t0ps3cret")])
+        .warm_cache(TOKEN)
+        .answers(&["@1", "@2"]);
+    sandbox.write_config(r#"{ "value_cache": { "items": ["GitHub"] } }"#);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard().as_deref(), Some("s3cret"));
+    let values = values(&sandbox).expect("values.json was written");
+    assert!(values.contains("\"i1\""), "{}", values);
+    assert!(!values.contains("s3cret"), "{}", values);
+
+    std::fs::write(sandbox.dir.join("offline"), "").unwrap();
+    std::fs::remove_file(sandbox.dir.join("op.log")).unwrap();
+    std::fs::remove_file(sandbox.dir.join("clipboard")).unwrap();
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "s3cret\n");
+    assert!(!sandbox.op_log().contains("get item"), "{}", sandbox.op_log());
+
+    // Items not listed still need op
+    let output = sandbox.run(&["get", "GitLab", "--field", "password"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("op can't reach 1Password"), "{}", stderr(&output));
+}
+
+#[test]
+fn excluded_vault_is_never_cached() {
+    let mut sandbox = Sandbox::new("value_cache_excluded");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN);
+    sandbox.write_config(r#"{ "value_cache": { "items": ["GitHub"], "exclude_vaults": ["vault"] } }"#);
+
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(common::stdout(&output), "s3cret\n");
+    assert_eq!(values(&sandbox), None);
+}
+
+#[test]
+fn expired_values_are_fetched_again() {
+    let mut sandbox = Sandbox::new("value_cache_expired");
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+        .warm_cache(TOKEN);
+    sandbox.write_config(r#"{ "value_cache": { "items": ["i1"], "ttl": 1 } }"#);
+
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    std::fs::remove_file(sandbox.dir.join("op.log")).unwrap();
+
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sandbox.op_log().contains("get item i1"), "{}", sandbox.op_log());
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use common::{Sandbox, stderr, stdout, PASSWORD, TOKEN};
+
+// Same title in a personal and a shared vault of one account
+fn two_vaults(name: &str) -> Sandbox {
+    let mut sandbox = Sandbox::new(name);
+    sandbox.op_items("my", &[("i1", "GitHub", "alice", "personal")])
+        .op_vault_items("my", ("shared", "Shared-Prod"), &[("i2", "GitHub", "alice", "production"), ("i3", "AWS", "root", "r00t")]);
+    sandbox
+}
+
+#[test]
+fn listing_items_records_their_vault() {
+    let mut sandbox = two_vaults("vault_names");
+    sandbox.answers(&[PASSWORD, "@2", "@2"]);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard().as_deref(), Some("production"));
+    assert!(sandbox.op_log().contains("list vaults --account my"), "{}", sandbox.op_log());
+    let items = &sandbox.cache()["accounts"][0]["items"];
+    assert_eq!(items[0]["vault_name"], "Private");
+    assert_eq!(items[1]["vault_name"], "Shared-Prod");
+}
+
+#[test]
+fn picker_shows_the_vault() {
+    let mut sandbox = two_vaults("vault_picker");
+    sandbox.warm_cache(TOKEN);
+
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "GitHub - alice (my/Private)\nGitHub - alice (my/Shared-Prod)\nAWS - root (my/Shared-Prod)\n");
+}
+
+#[test]
+fn vault_narrows_by_name_or_uuid() {
+    let mut sandbox = two_vaults("vault_filter");
+    sandbox.warm_cache(TOKEN);
+
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+
+    let output = sandbox.run(&["--vault", "shared-prod", "get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "production\n");
+
+    let output = sandbox.run(&["--vault", "vault", "get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "personal\n");
+
+    let output = sandbox.run(&["list", "--json"]);
+    let list: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(list[1]["vault"], "Shared-Prod");
+    assert_eq!(list[1]["vault_uuid"], "shared");
+}
+
+#[test]
+fn items_cached_without_vaults_are_listed_again() {
+    let mut sandbox = two_vaults("vault_old_cache");
+    sandbox.warm_cache(TOKEN);
+    // As written before vaults were recorded
+    let mut cache = sandbox.cache();
+    let account = cache["accounts"][0].as_object_mut().unwrap();
+    account.remove("items_version");
+    for item in account["items"].as_array_mut().unwrap() {
+        let item = item.as_object_mut().unwrap();
+        item.remove("vault_uuid");
+        item.remove("vault_name");
+    }
+    sandbox.write_cache(&cache.to_string());
+
+    let output = sandbox.run(&["--vault", "shared", "get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "production\n");
+    assert!(sandbox.op_log().contains("list items --account my"), "{}", sandbox.op_log());
+    let account = &sandbox.cache()["accounts"][0];
+    assert_eq!(account["items"][2]["vault_uuid"], "shared");
+    // Field lengths cached before survive listing again
+    assert_eq!(account["items"][2]["fields"][1]["value_length"], 4);
+
+    // Listed once, after that the cache is current
+    let output = sandbox.run(&["--vault", "shared", "list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.op_log().matches("list items").count(), 1, "{}", sandbox.op_log());
+}
+
+#[test]
+fn items_cached_without_vault_names_are_listed_again() {
+    let mut sandbox = two_vaults("vault_names_old_cache");
+    sandbox.warm_cache(TOKEN);
+    // As written when only vault UUIDs were recorded
+    let mut cache = sandbox.cache();
+    let account = cache["accounts"][0].as_object_mut().unwrap();
+    account["items_version"] = 1.into();
+    for item in account["items"].as_array_mut().unwrap() {
+        item.as_object_mut().unwrap().remove("vault_name");
+    }
+    sandbox.write_cache(&cache.to_string());
+
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "GitHub - alice (my/Private) [Login]\nGitHub - alice (my/Shared-Prod) [Login]\nAWS - root (my/Shared-Prod) [Login]\n");
+    assert!(sandbox.op_log().contains("list vaults --account my"), "{}", sandbox.op_log());
+}
+
+#[test]
+fn launcher_narrows_by_vault() {
+    let mut sandbox = two_vaults("vault_launcher");
+    sandbox.warm_cache(TOKEN)
+        .answers(&["~Shared-Prod", "@2", "@2"]);
+
+    let output = sandbox.run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard().as_deref(), Some("r00t"));
+}
+
+#[test]
+fn launcher_vault_filters_narrow_further() {
+    let mut sandbox = two_vaults("vault_launcher_twice");
+    sandbox.warm_cache(TOKEN)
+        .answers(&["~Shared-Prod", "~Private"]);
+
+    let output = sandbox.run(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No items match filter"), "{}", stderr(&output));
+    assert_eq!(sandbox.clipboard(), None);
+}
+
+#[test]
+fn configured_vaults_are_hidden_unless_asked_for() {
+    let mut sandbox = two_vaults("vault_rules");
+    sandbox.warm_cache(TOKEN);
+    sandbox.write_config(r#"{ "vaults": { "exclude": ["Shared-Prod"] } }"#);
+
+    let output = sandbox.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "GitHub - alice (my/Private)\n");
+
+    let output = sandbox.run(&["--vault", "Shared-Prod", "list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output).lines().count(), 2);
+
+    sandbox.write_config(r#"{ "vaults": { "include": ["shared"] } }"#);
+    let output = sandbox.run(&["get", "GitHub", "--field", "password"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "production\n");
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use std::convert::TryFrom;
+use std::process::{Child, Stdio};
+use std::time::{Duration, Instant};
+use zbus::blocking::Connection;
+use zbus::fdo;
+use zbus::zvariant::OwnedObjectPath;
+use common::{Bus, Sandbox, stderr, TOKEN};
+
+const OWN_SESSION: &str = "/org/freedesktop/login1/session/_31";
+const OTHER_SESSION: &str = "/org/freedesktop/login1/session/_32";
+const USER: &str = "/org/freedesktop/login1/user/_1000";
+
+/// logind's manager, with the watcher in a session or, like a service, outside of any
+struct Manager {
+    in_session: bool,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl Manager {
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, pid: u32) -> fdo::Result<OwnedObjectPath> {
+        match self.in_session {
+            true => Ok(OwnedObjectPath::try_from(OWN_SESSION).unwrap()),
+            false => Err(fdo::Error::Failed(format!("PID {} does not belong to any known session", pid))),
+        }
+    }
+
+    fn get_user(&self, _uid: u32) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(USER).unwrap()
+    }
+}
+
+struct User;
+
+#[zbus::interface(name = "org.freedesktop.login1.User")]
+impl User {
+    #[zbus(property)]
+    fn display(&self) -> (String, OwnedObjectPath) {
+        ("31".to_owned(), OwnedObjectPath::try_from(OWN_SESSION).unwrap())
+    }
+}
+
+/// `r1pw watch` on private system and session buses, over a warm cache
+struct Watcher {
+    sandbox: Sandbox,
+    process: Child,
+    _login1: Connection,
+    system_bus: Bus,
+    session_bus: Bus,
+}
+
+impl Watcher {
+    fn start(name: &str) -> Option<Watcher> {
+        Watcher::start_in(name, true)
+    }
+
+    fn start_in(name: &str, in_session: bool) -> Option<Watcher> {
+        let (system_bus, session_bus) = match (Bus::start(), Bus::start()) {
+            (Some(system_bus), Some(session_bus)) => (system_bus, session_bus),
+            _ => {
+                eprintln!("dbus-daemon not found, skipping");
+                return None
+            },
+        };
+        let login1 = zbus::blocking::connection::Builder::address(system_bus.address.as_str()).unwrap()
+            .name("org.freedesktop.login1").unwrap()
+            .serve_at("/org/freedesktop/login1", Manager { in_session }).unwrap()
+            .serve_at(USER, User).unwrap()
+            .build()
+            .unwrap();
+        let mut sandbox = Sandbox::new(name);
+        sandbox.op_items("my", &[("i1", "GitHub", "alice", "s3cret")])
+            .warm_cache(TOKEN);
+        let process = sandbox.command(&["watch"])
+            .env("DBUS_SYSTEM_BUS_ADDRESS", &system_bus.address)
+            .env("DBUS_SESSION_BUS_ADDRESS", &session_bus.address)
+            .spawn()
+            .unwrap();
+        Some(Watcher { sandbox, process, _login1: login1, system_bus, session_bus })
+    }
+
+    /// Sends a signal until the watcher has locked, as it subscribes some time after starting
+    fn locks_on(&mut self, system: bool, path: &str, member: &str, args: &[&str], within: Duration) -> bool {
+        let bus = if system { &self.system_bus } else { &self.session_bus };
+        let started = Instant::now();
+        let mut locked = false;
+        while !locked && started.elapsed() < within {
+            bus.signal(path, member, args);
+            std::thread::sleep(Duration::from_millis(100));
+            locked = self.sandbox.cache()["accounts"][0]["token"].is_null();
+        }
+        assert_eq!(self.process.try_wait().unwrap(), None, "watcher exited");
+        locked
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+const SECOND: Duration = Duration::from_secs(1);
+
+#[test]
+fn locks_before_sleep() {
+    if let Some(mut watcher) = Watcher::start("watch_sleep") {
+        assert!(watcher.locks_on(true, "/org/freedesktop/login1", "org.freedesktop.login1.Manager.PrepareForSleep", &["boolean:true"], 3 * SECOND));
+    }
+}
+
+#[test]
+fn does_not_lock_on_resume() {
+    if let Some(mut watcher) = Watcher::start("watch_resume") {
+        assert!(!watcher.locks_on(true, "/org/freedesktop/login1", "org.freedesktop.login1.Manager.PrepareForSleep", &["boolean:false"], SECOND));
+        // It was listening all along
+        assert!(watcher.locks_on(true, "/org/freedesktop/login1", "org.freedesktop.login1.Manager.PrepareForSleep", &["boolean:true"], 3 * SECOND));
+    }
+}
+
+#[test]
+fn locks_with_the_session() {
+    if let Some(mut watcher) = Watcher::start("watch_session_lock") {
+        assert!(watcher.locks_on(true, OWN_SESSION, "org.freedesktop.login1.Session.Lock", &[], 3 * SECOND));
+    }
+}
+
+#[test]
+fn other_sessions_locking_are_ignored() {
+    if let Some(mut watcher) = Watcher::start("watch_other_session") {
+        assert!(!watcher.locks_on(true, OTHER_SESSION, "org.freedesktop.login1.Session.Lock", &[], SECOND));
+        assert!(watcher.locks_on(true, OWN_SESSION, "org.freedesktop.login1.Session.Lock", &[], 3 * SECOND));
+    }
+}
+
+#[test]
+fn as_a_service_locks_with_the_graphical_session() {
+    if let Some(mut watcher) = Watcher::start_in("watch_service", false) {
+        assert!(!watcher.locks_on(true, OTHER_SESSION, "org.freedesktop.login1.Session.Lock", &[], SECOND));
+        assert!(watcher.locks_on(true, OWN_SESSION, "org.freedesktop.login1.Session.Lock", &[], 3 * SECOND));
+    }
+}
+
+#[test]
+fn locks_when_the_screensaver_starts() {
+    if let Some(mut watcher) = Watcher::start("watch_screensaver") {
+        assert!(watcher.locks_on(false, "/org/freedesktop/ScreenSaver", "org.freedesktop.ScreenSaver.ActiveChanged", &["boolean:true"], 3 * SECOND));
+    }
+}
+
+#[test]
+fn keeps_watching_without_a_system_bus() {
+    let session_bus = match Bus::start() {
+        Some(bus) => bus,
+        None => return eprintln!("dbus-daemon not found, skipping"),
+    };
+    let sandbox = Sandbox::new("watch_no_system_bus");
+    let mut watcher = sandbox.command(&["watch"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", "unix:path=/nonexistent")
+        .env("DBUS_SESSION_BUS_ADDRESS", &session_bus.address)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(watcher.try_wait().unwrap(), None, "watcher exited");
+    watcher.kill().unwrap();
+
+    let output = sandbox.command(&["watch"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", "unix:path=/nonexistent")
+        .env("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("nothing to watch"), "{}", stderr(&output));
+}